@@ -57,9 +57,8 @@ where
     O: Write,
     E: Write,
 {
+    let mut repo = ctx.repo();
     let working_dir = ctx.dir;
-    let root_path = working_dir.as_path();
-    let mut repo = Repository::new(&root_path);
     let options = ctx.options.as_ref().unwrap();
     let args: Vec<_> = if let Some(args) = options.values_of("args") {
         args.collect()
@@ -96,6 +95,12 @@ where
     }
 
     for pathname in paths {
+        // Flagged entries are left as they are, without reading the
+        // file
+        match repo.index.entry_for_path(&pathname) {
+            Some(entry) if entry.skips_workspace_checks() => continue,
+            _ => (),
+        }
         add_to_index(&mut repo, &pathname)?;
     }
 
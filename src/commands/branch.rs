@@ -1,5 +1,4 @@
-use crate::commands::CommandContext;
-use crate::database::object::Object;
+use crate::commands::{paint, CommandContext};
 use crate::database::{Database, ParsedObject};
 use crate::pager::Pager;
 use crate::refs::Ref;
@@ -25,9 +24,7 @@ where
     E: Write,
 {
     pub fn new(ctx: CommandContext<'a, I, O, E>) -> Branch<'a, I, O, E> {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
+        let repo = ctx.repo();
 
         Branch { repo, ctx }
     }
@@ -54,7 +51,11 @@ where
 
     fn list_branches(&mut self) -> Result<(), String> {
         let current = self.repo.refs.current_ref("HEAD");
-        let mut branches = self.repo.refs.list_branches();
+        let mut branches = self
+            .repo
+            .refs
+            .list_branches()
+            .map_err(|e| format!("fatal: {}\n", e))?;
         branches.sort();
 
         let max_width = branches
@@ -63,12 +64,14 @@ where
             .max()
             .unwrap_or(0);
 
-        Pager::setup_pager();
+        if self.ctx.use_pager {
+            Pager::setup_pager(self.ctx.env);
+        }
 
         for r#ref in branches {
             let info = self.format_ref(&r#ref, &current);
             let extended_info = self.extended_branch_info(&r#ref, max_width);
-            println!("{}{}", info, extended_info);
+            writeln!(self.ctx.stdout, "{}{}", info, extended_info).map_err(|e| e.to_string())?;
         }
 
         Ok(())
@@ -76,7 +79,13 @@ where
 
     fn format_ref(&self, r#ref: &Ref, current: &Ref) -> String {
         if r#ref == current {
-            format!("* {}", self.repo.refs.ref_short_name(r#ref).green())
+            format!(
+                "* {}",
+                paint(
+                    self.ctx.use_color,
+                    self.repo.refs.ref_short_name(r#ref).green()
+                )
+            )
         } else {
             format!("  {}", self.repo.refs.ref_short_name(r#ref))
         }
@@ -95,19 +104,18 @@ where
                 .refs
                 .read_oid(r#ref)
                 .expect("unable to resolve branch to oid");
-            let commit = if let ParsedObject::Commit(commit) = self.repo.database.load(&oid) {
-                commit
+            let title = if let ParsedObject::Commit(commit) = self.repo.database.load(&oid) {
+                commit.title_line()
             } else {
                 panic!("branch ref was not pointing to commit");
             };
-            let oid = commit.get_oid();
-            let short = Database::short_oid(&oid);
+            let short = self.repo.database.abbrev(&oid);
             let ref_short_name = self.repo.refs.ref_short_name(r#ref);
             format!(
                 "{:width$}{} {}",
                 " ",
                 short,
-                commit.title_line(),
+                title,
                 width = (max_width - ref_short_name.len() + 1)
             )
         } else {
@@ -123,7 +131,7 @@ where
         let start_point = if start_point.is_none() {
             self.repo.refs.read_head().expect("empty HEAD")
         } else {
-            match Revision::new(&mut self.repo, start_point.unwrap()).resolve() {
+            match Revision::new(&mut self.repo, start_point.unwrap())?.resolve() {
                 Ok(rev) => rev,
                 Err(errors) => {
                     let mut v = vec![];
@@ -167,10 +175,217 @@ where
         let oid = self.repo.refs.delete_branch(branch_name)?;
         let short = Database::short_oid(&oid);
 
-        println!(
+        writeln!(
+            self.ctx.stdout,
             "Deleted branch {} (was {})",
             branch_name, short
-        );
+        )
+        .map_err(|e| e.to_string())?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::object::Object;
+    use crate::database::tag::Tag;
+
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+    }
+
+    /// Make three commits on master and return their IDs, oldest first
+    fn commit_history(cmd_helper: &mut CommandHelper) -> Vec<String> {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+
+        let mut graph = GraphBuilder::new(cmd_helper);
+        graph
+            .commit("first", &[])
+            .commit("second", &["first"])
+            .commit("third", &["second"])
+            .branch("master", "third");
+        vec![graph.oid("first"), graph.oid("second"), graph.oid("third")]
+    }
+
+    fn assert_branch_at(cmd_helper: &mut CommandHelper, revision: &str, oid: &str) {
+        cmd_helper.delete(".git/refs/heads/topic").unwrap_or(());
+        cmd_helper.jit_cmd(&["branch", "topic", revision]).unwrap();
+        assert_eq!(
+            oid,
+            cmd_helper
+                .read_file(".git/refs/heads/topic")
+                .unwrap()
+                .trim()
+        );
+    }
+
+    #[test]
+    fn creates_branch_from_abbreviated_oid() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let oid = cmd_helper.read_file(".git/refs/heads/master").unwrap();
+
+        cmd_helper.jit_cmd(&["branch", "topic", &oid[..5]]).unwrap();
+
+        assert_eq!(oid, cmd_helper.read_file(".git/refs/heads/topic").unwrap());
+    }
+
+    #[test]
+    fn fails_to_create_branch_from_unknown_revision() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert!(cmd_helper.jit_cmd(&["branch", "topic", "no-such-rev"]).is_err());
+        assert!(cmd_helper.jit_cmd(&["branch", "topic", "0000000"]).is_err());
+    }
+
+    #[test]
+    fn fails_to_create_branch_from_invalid_revision() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        for revision in &["HEAD^99999999999", "HEAD~99999999999", "HEAD^x"] {
+            assert_output(
+                &cmd_helper
+                    .jit_cmd(&["branch", "topic", revision])
+                    .unwrap_err(),
+                &format!("fatal: invalid object name '{}'\n", revision),
+            );
+        }
+    }
+
+    #[test]
+    fn fails_to_peel_tags_of_missing_objects() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let missing = "1".repeat(40);
+        let tag = Tag::new(&missing, "commit", "v1.0", None, "Version 1.0\n");
+        cmd_helper.repo().database.store(&tag).unwrap();
+        cmd_helper
+            .write_file(".git/refs/tags/v1.0", tag.get_oid().as_bytes())
+            .unwrap();
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["branch", "topic", "v1.0"])
+                .unwrap_err(),
+            &format!("error: unable to read object {}\n\n", missing),
+        );
+    }
+
+    #[test]
+    fn resolves_parents_and_ancestors() {
+        let mut cmd_helper = CommandHelper::new();
+        let oids = commit_history(&mut cmd_helper);
+
+        assert_branch_at(&mut cmd_helper, "HEAD^", &oids[1]);
+        assert_branch_at(&mut cmd_helper, "HEAD^1", &oids[1]);
+        assert_branch_at(&mut cmd_helper, "@^0", &oids[2]);
+        assert_branch_at(&mut cmd_helper, "master~", &oids[1]);
+        assert_branch_at(&mut cmd_helper, "master~2", &oids[0]);
+        assert_branch_at(&mut cmd_helper, "@~1^", &oids[0]);
+
+        assert!(cmd_helper.jit_cmd(&["branch", "other", "HEAD^2"]).is_err());
+        assert!(cmd_helper.jit_cmd(&["branch", "other", "HEAD~3"]).is_err());
+    }
+
+    #[test]
+    fn resolves_parents_of_merges() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        let mut graph = GraphBuilder::new(&cmd_helper);
+        graph
+            .commit("A", &[])
+            .commit("B", &["A"])
+            .commit("C", &["A"])
+            .commit("D", &["B", "C"])
+            .branch("master", "D");
+
+        assert_branch_at(&mut cmd_helper, "HEAD^", &graph.oid("B"));
+        assert_branch_at(&mut cmd_helper, "HEAD^2", &graph.oid("C"));
+        assert_branch_at(&mut cmd_helper, "HEAD^2~", &graph.oid("A"));
+        assert!(cmd_helper.jit_cmd(&["branch", "other", "HEAD^3"]).is_err());
+    }
+
+    #[test]
+    fn resolves_commit_message_searches() {
+        let mut cmd_helper = CommandHelper::new();
+        let oids = commit_history(&mut cmd_helper);
+
+        assert_branch_at(&mut cmd_helper, ":/sec", &oids[1]);
+        assert_branch_at(&mut cmd_helper, ":/^(first|second)", &oids[1]);
+        assert!(cmd_helper.jit_cmd(&["branch", "other", ":/nothing"]).is_err());
+    }
+
+    #[test]
+    fn resolves_upstream_from_branch_config() {
+        let mut cmd_helper = CommandHelper::new();
+        let oids = commit_history(&mut cmd_helper);
+
+        assert!(cmd_helper.jit_cmd(&["branch", "other", "@{u}"]).is_err());
+
+        cmd_helper
+            .write_file(".git/refs/remotes/origin/main", oids[0].as_bytes())
+            .unwrap();
+        cmd_helper
+            .write_file(
+                ".git/config",
+                b"[branch \"master\"]\n\tremote = origin\n\tmerge = refs/heads/main\n",
+            )
+            .unwrap();
+
+        assert_branch_at(&mut cmd_helper, "@{u}", &oids[0]);
+        assert_branch_at(&mut cmd_helper, "master@{upstream}", &oids[0]);
+        assert!(cmd_helper.jit_cmd(&["branch", "other", "topic@{u}"]).is_err());
+    }
+
+    #[test]
+    fn peels_upstreams_that_point_at_tags() {
+        let mut cmd_helper = CommandHelper::new();
+        let oids = commit_history(&mut cmd_helper);
+
+        let tag = Tag::new(&oids[0], "commit", "v1.0", None, "Version 1.0\n");
+        cmd_helper.repo().database.store(&tag).unwrap();
+        cmd_helper
+            .write_file(".git/refs/remotes/origin/main", tag.get_oid().as_bytes())
+            .unwrap();
+        cmd_helper
+            .write_file(
+                ".git/config",
+                b"[branch \"master\"]\n\tremote = origin\n\tmerge = refs/heads/main\n",
+            )
+            .unwrap();
+
+        assert_branch_at(&mut cmd_helper, "@{u}", &oids[0]);
+        assert_branch_at(&mut cmd_helper, "@{u}^0", &oids[0]);
+    }
+
+    #[test]
+    fn refuses_non_commit_objects_from_tree_paths() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        for message in &["first", "second"] {
+            cmd_helper.write_file("dir/file.txt", message.as_bytes()).unwrap();
+            cmd_helper.jit_cmd(&["add", "."]).unwrap();
+            cmd_helper.commit(message);
+        }
+
+        let error = cmd_helper
+            .jit_cmd(&["branch", "other", "HEAD~1:dir/file.txt"])
+            .unwrap_err();
+        assert!(error.contains("is a blob, not a commit"));
+
+        let error = cmd_helper.jit_cmd(&["branch", "other", "HEAD:dir"]).unwrap_err();
+        assert!(error.contains("is a tree, not a commit"));
+
+        let error = cmd_helper
+            .jit_cmd(&["branch", "other", "HEAD:missing.txt"])
+            .unwrap_err();
+        assert!(error.contains("path 'missing.txt' does not exist"));
+    }
+}
@@ -2,7 +2,7 @@ use crate::commands::CommandContext;
 use crate::database::object::Object;
 use crate::database::tree::TreeEntry;
 use crate::database::tree_diff::TreeDiff;
-use crate::database::{Database, ParsedObject};
+use crate::database::{Database, Entry, ParsedObject};
 use crate::refs::Ref;
 use crate::repository::Repository;
 use crate::revision::Revision;
@@ -38,9 +38,7 @@ where
     E: Write,
 {
     pub fn new(ctx: CommandContext<'a, I, O, E>) -> Checkout<'a, I, O, E> {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
+        let repo = ctx.repo();
 
         Checkout { repo, ctx }
     }
@@ -53,10 +51,12 @@ where
         let oid = commit.get_oid();
         let short = Database::short_oid(&oid);
 
-        println!(
+        writeln!(
+            self.ctx.stdout,
             "{}",
             format!("{} {} {}", message, short, commit.title_line())
-        );
+        )
+        .map_err(|e| e.to_string())?;
         Ok(())
     }
 
@@ -79,14 +79,16 @@ where
         new_ref: &Ref,
     ) -> Result<(), String> {
         if new_ref.is_head() && !current_ref.is_head() {
-            println!(
+            writeln!(
+                self.ctx.stdout,
                 "{}
 
 {}
 ",
                 format!("Note: checking out '{}'.", target),
                 DETACHED_HEAD_MESSAGE
-            );
+            )
+            .map_err(|e| e.to_string())?;
         }
         Ok(())
     }
@@ -101,12 +103,15 @@ where
         if new_ref.is_head() {
             self.print_head_position("HEAD is now at", target_oid)?;
         } else if new_ref == current_ref {
-            eprintln!("{}", format!("Already on {}", target));
+            writeln!(self.ctx.stderr, "{}", format!("Already on {}", target))
+                .map_err(|e| e.to_string())?;
         } else {
-            eprintln!(
+            writeln!(
+                self.ctx.stderr,
                 "{}",
                 format!("Switched to branch {}", target)
-            );
+            )
+            .map_err(|e| e.to_string())?;
         }
         Ok(())
     }
@@ -132,7 +137,7 @@ where
             .read_oid(&current_ref)
             .unwrap_or_else(|| panic!("failed to read ref: {:?}", current_ref));
 
-        let mut revision = Revision::new(&mut self.repo, target);
+        let mut revision = Revision::new(&mut self.repo, target)?;
         let target_oid = match revision.resolve() {
             Ok(oid) => oid,
             Err(errors) => {
@@ -150,8 +155,13 @@ where
             }
         };
 
-        let tree_diff = self.tree_diff(&current_oid, &target_oid);
-        let mut migration = self.repo.migration(tree_diff);
+        let force = options.is_present("force");
+        let mut tree_diff = self.tree_diff(&current_oid, &target_oid);
+        if force {
+            self.discard_local_changes(&mut tree_diff)?;
+        }
+
+        let mut migration = self.repo.migration(tree_diff, force);
         migration.apply_changes()?;
 
         self.repo.index.write_updates().map_err(|e| e.to_string())?;
@@ -168,6 +178,37 @@ where
         Ok(())
     }
 
+    /// Add every path with uncommitted changes to `tree_diff`, so the
+    /// migration resets it to the target commit. Paths the diff
+    /// doesn't already cover are the same in HEAD and the target.
+    fn discard_local_changes(
+        &mut self,
+        tree_diff: &mut HashMap<PathBuf, (Option<TreeEntry>, Option<TreeEntry>)>,
+    ) -> Result<(), String> {
+        self.repo.initialize_status()?;
+
+        for path in self.repo.changed.clone() {
+            let path_buf = PathBuf::from(&path);
+            if tree_diff.contains_key(&path_buf) {
+                continue;
+            }
+
+            let item = self.repo.head_tree.get(&path).cloned();
+            let old_item = item.clone().or_else(|| {
+                self.repo
+                    .index
+                    .entry_for_path(&path)
+                    .map(|entry| TreeEntry::Entry(Entry::from(entry)))
+            });
+
+            if old_item.is_some() {
+                tree_diff.insert(path_buf, (old_item, item));
+            }
+        }
+
+        Ok(())
+    }
+
     fn tree_diff(
         &mut self,
         a: &str,
@@ -256,6 +297,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn fails_for_invalid_revisions() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["checkout", "@^99999999999"])
+                .unwrap_err(),
+            "fatal: invalid object name '@^99999999999'\n",
+        );
+        cmd_helper.assert_workspace(BASE_FILES.clone());
+    }
+
     #[test]
     fn updates_a_changed_file() {
         let mut cmd_helper = CommandHelper::new();
@@ -992,4 +1047,73 @@ A  outer/inner/4.txt\n",
         );
     }
 
+    #[test]
+    fn lists_all_conflicting_files_in_order() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+        cmd_helper.write_file("outer/2.txt", b"changed").unwrap();
+        commit_all(&mut cmd_helper);
+
+        cmd_helper.write_file("outer/2.txt", b"conflict").unwrap();
+        cmd_helper.write_file("1.txt", b"conflict").unwrap();
+
+        assert_stale_file(
+            cmd_helper.jit_cmd(&["checkout", "@^"]),
+            "1.txt\n\touter/2.txt",
+        );
+    }
+
+    #[test]
+    fn force_overwrites_a_modified_file() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+        commit_all(&mut cmd_helper);
+
+        cmd_helper.write_file("1.txt", b"conflict").unwrap();
+        cmd_helper.jit_cmd(&["checkout", "-f", "@^"]).unwrap();
+
+        cmd_helper.assert_workspace(BASE_FILES.clone());
+        cmd_helper.clear_stdout();
+        cmd_helper.assert_status("");
+    }
+
+    #[test]
+    fn force_overwrites_an_untracked_file() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.write_file("outer/94.txt", b"94").unwrap();
+        commit_all(&mut cmd_helper);
+        cmd_helper.jit_cmd(&["checkout", "@^"]).unwrap();
+
+        cmd_helper.write_file("outer/94.txt", b"untracked").unwrap();
+        cmd_helper.jit_cmd(&["checkout", "--force", "master"]).unwrap();
+
+        let mut expected_workspace = BASE_FILES.clone();
+        expected_workspace.insert("outer/94.txt", "94");
+        cmd_helper.assert_workspace(expected_workspace);
+    }
+
+    #[test]
+    fn force_discards_changes_to_files_not_in_the_diff() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+        commit_all(&mut cmd_helper);
+
+        cmd_helper.write_file("outer/2.txt", b"hello").unwrap();
+        cmd_helper.delete("outer/inner/3.txt").unwrap();
+        cmd_helper.write_file("outer/4.txt", b"staged").unwrap();
+        cmd_helper.jit_cmd(&["add", "outer/4.txt"]).unwrap();
+        cmd_helper.write_file("untracked.txt", b"kept").unwrap();
+
+        cmd_helper.jit_cmd(&["checkout", "-f", "@^"]).unwrap();
+
+        let mut expected_workspace = BASE_FILES.clone();
+        expected_workspace.insert("untracked.txt", "kept");
+        cmd_helper.assert_workspace(expected_workspace);
+        cmd_helper.clear_stdout();
+        cmd_helper.assert_status("?? untracked.txt\n");
+    }
 }
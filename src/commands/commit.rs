@@ -7,7 +7,6 @@ use crate::database::commit::{Author, Commit};
 use crate::database::object::Object;
 use crate::database::tree::Tree;
 use crate::database::Entry;
-use crate::repository::Repository;
 
 pub fn commit_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), String>
 where
@@ -15,11 +14,9 @@ where
     O: Write,
     E: Write,
 {
-    let working_dir = ctx.dir;
-    let root_path = working_dir.as_path();
-    let mut repo = Repository::new(&root_path);
+    let mut repo = ctx.repo();
 
-    repo.index.load().expect("loading .git/index failed");
+    repo.index.load().map_err(|e| format!("fatal: {}\n", e))?;
     let entries: Vec<Entry> = repo
         .index
         .entries
@@ -37,28 +34,36 @@ where
     let author_name = ctx
         .env
         .get("GIT_AUTHOR_NAME")
-        .expect("GIT_AUTHOR_NAME not set");
+        .ok_or("fatal: GIT_AUTHOR_NAME not set\n")?;
     let author_email = ctx
         .env
         .get("GIT_AUTHOR_EMAIL")
-        .expect("GIT_AUTHOR_EMAIL not set");
+        .ok_or("fatal: GIT_AUTHOR_EMAIL not set\n")?;
 
-    let author = Author {
-        name: author_name.to_string(),
-        email: author_email.to_string(),
-        time: Utc::now().with_timezone(&FixedOffset::east(0)),
-    };
+    let author = Author::new(
+        author_name,
+        author_email,
+        Utc::now().with_timezone(&FixedOffset::east(0)),
+    );
 
     let mut commit_message = String::new();
     ctx.stdin
         .read_to_string(&mut commit_message)
-        .expect("reading commit from STDIN failed");
+        .map_err(|e| format!("fatal: {}\n", e))?;
 
-    let commit = Commit::new(&parent, root.get_oid(), author, commit_message);
-    repo.database.store(&commit).expect("writing commit failed");
+    let commit = Commit::new(
+        parent.iter().cloned().collect(),
+        root.get_oid(),
+        author.clone(),
+        author,
+        commit_message,
+    );
+    repo.database
+        .store(&commit)
+        .map_err(|e| format!("fatal: {}\n", e))?;
     repo.refs
         .update_head(&commit.get_oid())
-        .expect("updating HEAD failed");
+        .map_err(|e| format!("fatal: {}\n", e))?;
 
     let commit_prefix = if parent.is_some() {
         ""
@@ -66,7 +71,50 @@ where
         "(root-commit) "
     };
 
-    println!("[{}{}] {}", commit_prefix, commit.get_oid(), commit.message);
+    writeln!(
+        ctx.stdout,
+        "[{}{}] {}",
+        commit_prefix,
+        commit.get_oid(),
+        commit.message
+    )
+    .map_err(|e| e.to_string())?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::ParsedObject;
+
+    #[test]
+    fn records_head_as_the_parent() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+        cmd_helper.write_file("file.txt", b"two").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("second");
+
+        let mut repo = cmd_helper.repo();
+        let head = repo.refs.read_head().unwrap();
+        let parent = match repo.database.load(&head) {
+            ParsedObject::Commit(commit) => {
+                assert_eq!("second", commit.message);
+                assert_eq!("author@example.com", commit.committer.email);
+                commit.parents[0].clone()
+            }
+            _ => unreachable!(),
+        };
+        match repo.database.load(&parent) {
+            ParsedObject::Commit(commit) => {
+                assert_eq!("first", commit.message);
+                assert!(commit.parents.is_empty());
+            }
+            _ => unreachable!(),
+        }
+    }
+}
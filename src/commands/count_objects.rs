@@ -0,0 +1,165 @@
+use crate::commands::CommandContext;
+use crate::repository::Repository;
+use std::io::{Read, Write};
+
+pub struct CountObjects<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> CountObjects<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> CountObjects<'a, I, O, E> {
+        let repo = ctx.repo();
+
+        CountObjects { repo, ctx }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let verbose = self
+            .ctx
+            .options
+            .as_ref()
+            .map(|o| o.is_present("verbose"))
+            .unwrap_or(false);
+
+        let counts = self
+            .repo
+            .database
+            .count_objects()
+            .map_err(|e| e.to_string())?;
+
+        if !verbose {
+            writeln!(
+                self.ctx.stdout,
+                "{} objects, {} kilobytes",
+                counts.count,
+                counts.size / 1024
+            )
+            .map_err(|e| e.to_string())?;
+            return Ok(());
+        }
+
+        writeln!(self.ctx.stdout, "count: {}", counts.count).map_err(|e| e.to_string())?;
+        writeln!(self.ctx.stdout, "size: {}", counts.size / 1024).map_err(|e| e.to_string())?;
+        writeln!(self.ctx.stdout, "in-pack: {}", counts.in_pack).map_err(|e| e.to_string())?;
+        writeln!(self.ctx.stdout, "packs: {}", counts.packs).map_err(|e| e.to_string())?;
+        writeln!(self.ctx.stdout, "size-pack: {}", counts.size_pack / 1024)
+            .map_err(|e| e.to_string())?;
+        writeln!(self.ctx.stdout, "prune-packable: {}", counts.prune_packable)
+            .map_err(|e| e.to_string())?;
+        writeln!(self.ctx.stdout, "garbage: {}", counts.garbage).map_err(|e| e.to_string())?;
+        writeln!(
+            self.ctx.stdout,
+            "size-garbage: {}",
+            counts.size_garbage / 1024
+        )
+        .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::pack_index::tests::v2_index;
+    use std::collections::HashMap;
+
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+    }
+
+    /// Parse `count-objects -v` output, leaving out the sizes since
+    /// they depend on the filesystem's block size
+    fn counts(cmd_helper: &mut CommandHelper) -> HashMap<String, String> {
+        let (stdout, _) = cmd_helper.jit_cmd(&["count-objects", "-v"]).unwrap();
+        stdout
+            .lines()
+            .map(|line| {
+                let mut parts = line.splitn(2, ": ");
+                (
+                    parts.next().unwrap().to_string(),
+                    parts.next().unwrap().to_string(),
+                )
+            })
+            .filter(|(key, _)| !key.starts_with("size"))
+            .collect()
+    }
+
+    fn expected(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn counts_loose_objects() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["count-objects"]).unwrap();
+        assert!(stdout.starts_with("3 objects, "));
+
+        assert_eq!(
+            expected(&[
+                ("count", "3"),
+                ("in-pack", "0"),
+                ("packs", "0"),
+                ("prune-packable", "0"),
+                ("garbage", "0"),
+            ]),
+            counts(&mut cmd_helper)
+        );
+    }
+
+    #[test]
+    fn counts_packs_and_garbage() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let commit_oid = cmd_helper.read_file(".git/refs/heads/master").unwrap();
+
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.pack", b"PACK")
+            .unwrap();
+        cmd_helper
+            .write_file(
+                ".git/objects/pack/pack-1.idx",
+                &v2_index(&[
+                    commit_oid.trim(),
+                    "1234567890123456789012345678901234567890",
+                ]),
+            )
+            .unwrap();
+        cmd_helper
+            .write_file(".git/objects/pack/pack-2.idx", b"")
+            .unwrap();
+        cmd_helper
+            .write_file(".git/objects/12/not-an-object", b"")
+            .unwrap();
+
+        assert_eq!(
+            expected(&[
+                ("count", "3"),
+                ("in-pack", "2"),
+                ("packs", "1"),
+                ("prune-packable", "1"),
+                ("garbage", "2"),
+            ]),
+            counts(&mut cmd_helper)
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::commands::CommandContext;
+use crate::commands::{paint, CommandContext};
 use crate::database::blob::Blob;
 use crate::database::object::Object;
 use crate::database::{Database, ParsedObject};
@@ -37,9 +37,7 @@ where
     E: Write,
 {
     pub fn new(ctx: CommandContext<'a, I, O, E>) -> Diff<'a, I, O, E> {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
+        let repo = ctx.repo();
 
         Diff { ctx, repo }
     }
@@ -48,7 +46,9 @@ where
         self.repo.index.load().map_err(|e| e.to_string())?;
         self.repo.initialize_status()?;
 
-        Pager::setup_pager();
+        if self.ctx.use_pager {
+            Pager::setup_pager(self.ctx.env);
+        }
 
         if self
             .ctx
@@ -114,7 +114,15 @@ where
         a.path = format!("a/{}", a.path);
         b.path = format!("b/{}", b.path);
 
-        println!("{}", format!("diff --git {} {}", a.path, b.path).bold());
+        writeln!(
+            self.ctx.stdout,
+            "{}",
+            paint(
+                self.ctx.use_color,
+                format!("diff --git {} {}", a.path, b.path).bold()
+            )
+        )
+        .map_err(|e| e.to_string())?;
 
         self.print_diff_mode(&a, &b)?;
         self.print_diff_content(&a, &b)
@@ -122,25 +130,45 @@ where
 
     fn print_diff_mode(&mut self, a: &Target, b: &Target) -> Result<(), String> {
         if a.mode == None {
-            println!(
+            writeln!(
+                self.ctx.stdout,
                 "{}",
-                format!("new file mode {:o}", b.mode.expect("missing mode")).bold()
-            );
+                paint(
+                    self.ctx.use_color,
+                    format!("new file mode {:o}", b.mode.expect("missing mode")).bold()
+                )
+            )
+            .map_err(|e| e.to_string())?;
         } else if b.mode == None {
-            println!(
+            writeln!(
+                self.ctx.stdout,
                 "{}",
-                format!("deleted file mode {:o}", a.mode.expect("missing mode")).bold()
-            );
+                paint(
+                    self.ctx.use_color,
+                    format!("deleted file mode {:o}", a.mode.expect("missing mode")).bold()
+                )
+            )
+            .map_err(|e| e.to_string())?;
         } else if a.mode != b.mode {
-            println!(
+            writeln!(
+                self.ctx.stdout,
                 "{}",
-                format!("old mode {:o}", a.mode.expect("missing mode")).bold()
-            );
+                paint(
+                    self.ctx.use_color,
+                    format!("old mode {:o}", a.mode.expect("missing mode")).bold()
+                )
+            )
+            .map_err(|e| e.to_string())?;
 
-            println!(
+            writeln!(
+                self.ctx.stdout,
                 "{}",
-                format!("new mode {:o}", b.mode.expect("missing mode")).bold()
-            );
+                paint(
+                    self.ctx.use_color,
+                    format!("new mode {:o}", b.mode.expect("missing mode")).bold()
+                )
+            )
+            .map_err(|e| e.to_string())?;
         }
 
         Ok(())
@@ -151,22 +179,37 @@ where
             return Ok(());
         }
 
-        println!(
+        writeln!(
+            self.ctx.stdout,
             "{}",
-            format!(
-                "index {}..{}{}",
-                short(&a.oid),
-                short(&b.oid),
-                if a.mode == b.mode {
-                    format!(" {:o}", a.mode.expect("Missing mode"))
-                } else {
-                    "".to_string()
-                }
+            paint(
+                self.ctx.use_color,
+                format!(
+                    "index {}..{}{}",
+                    short(&a.oid),
+                    short(&b.oid),
+                    if a.mode == b.mode {
+                        format!(" {:o}", a.mode.expect("Missing mode"))
+                    } else {
+                        "".to_string()
+                    }
+                )
+                .bold()
             )
-            .bold()
-        );
-        println!("{}", format!("--- {}", a.path).bold());
-        println!("{}", format!("+++ {}", b.path).bold());
+        )
+        .map_err(|e| e.to_string())?;
+        writeln!(
+            self.ctx.stdout,
+            "{}",
+            paint(self.ctx.use_color, format!("--- {}", a.path).bold())
+        )
+        .map_err(|e| e.to_string())?;
+        writeln!(
+            self.ctx.stdout,
+            "{}",
+            paint(self.ctx.use_color, format!("+++ {}", b.path).bold())
+        )
+        .map_err(|e| e.to_string())?;
 
         let hunks = diff::Diff::diff_hunks(&a.data, &b.data);
         for h in hunks {
@@ -182,13 +225,23 @@ where
             EditType::Del => format!("{}", edit).red(),
             EditType::Eql => format!("{}", edit).normal(),
         };
-        println!("{}", edit_string);
+        writeln!(
+            self.ctx.stdout,
+            "{}",
+            paint(self.ctx.use_color, edit_string)
+        )
+        .map_err(|e| e.to_string())?;
 
         Ok(())
     }
 
     fn print_diff_hunk(&mut self, hunk: diff::Hunk) -> Result<(), String> {
-        println!("{}", hunk.header().cyan());
+        writeln!(
+            self.ctx.stdout,
+            "{}",
+            paint(self.ctx.use_color, hunk.header().cyan())
+        )
+        .map_err(|e| e.to_string())?;
 
         for edit in hunk.edits {
             self.print_diff_edit(edit).map_err(|e| e.to_string())?;
@@ -275,3 +328,24 @@ where
 fn short(oid: &str) -> &str {
     Database::short_oid(oid)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    #[test]
+    fn prints_plain_text_without_colour() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one\ntwo\n").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+        cmd_helper.write_file("file.txt", b"one\nthree\n").unwrap();
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["diff"]).unwrap();
+
+        assert!(stdout.contains("\n@@ -1,"));
+        assert!(stdout.contains("\n-two\n+three\n"));
+        assert!(!stdout.contains("\x1b["));
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::prelude::*;
+
+use crate::commands::tests::CommandHelper;
+use crate::database::blob::Blob;
+use crate::database::commit::{Author, Commit};
+use crate::database::object::Object;
+use crate::database::tree::Tree;
+use crate::database::{Entry, ParsedObject};
+use crate::repository::Repository;
+
+// 2017-07-14T02:40:00Z, so that commit IDs are the same on every run
+const START_TIME: i64 = 1_500_000_000;
+
+/// Writes commit graphs straight into a test repository's object
+/// database and refs, bypassing the workspace and index. Commits are
+/// named, and each one's tree holds a single `file.txt` containing
+/// its name. Every commit is a minute newer than the one before.
+///
+/// The graph is written to the helper's repository on disk rather than
+/// kept in memory: commands open their repository from the context's
+/// directory and `GIT_DIR` like the binary does, so a graph they can
+/// read has to be there. The commands themselves still run in-process
+/// through `run_command`.
+///
+///     let mut graph = GraphBuilder::new(&cmd_helper);
+///     graph
+///         .commit("A", &[])
+///         .commit("B", &["A"])
+///         .commit("C", &["A"])
+///         .commit("D", &["B", "C"])
+///         .branch("master", "D");
+pub struct GraphBuilder {
+    repo: Repository,
+    git_path: PathBuf,
+    oids: HashMap<String, String>,
+    time: i64,
+}
+
+impl GraphBuilder {
+    /// The repository must already have been initialized
+    pub fn new(cmd_helper: &CommandHelper) -> GraphBuilder {
+        GraphBuilder {
+            repo: cmd_helper.repo(),
+            git_path: cmd_helper.git_path(),
+            oids: HashMap::new(),
+            time: START_TIME,
+        }
+    }
+
+    pub fn commit(&mut self, name: &str, parents: &[&str]) -> &mut GraphBuilder {
+        let blob = Blob::new(name.as_bytes());
+        self.repo.database.store(&blob).unwrap();
+
+        let tree = Tree::build(&[Entry::new("file.txt", &blob.get_oid(), 0o100644)]);
+        self.repo.database.store(&tree).unwrap();
+
+        let time = FixedOffset::east(0).timestamp(self.time, 0);
+        self.time += 60;
+        let author = Author::new("A. U. Thor", "author@example.com", time);
+
+        let commit = Commit::new(
+            parents.iter().map(|parent| self.oid(parent)).collect(),
+            tree.get_oid(),
+            author.clone(),
+            author,
+            format!("{}\n", name),
+        );
+        self.repo.database.store(&commit).unwrap();
+        self.oids.insert(name.to_string(), commit.get_oid());
+
+        self
+    }
+
+    /// Point `refs/heads/<branch>` at the commit `name`
+    pub fn branch(&mut self, branch: &str, name: &str) -> &mut GraphBuilder {
+        let path = self.git_path.join("refs/heads").join(branch);
+        self.repo
+            .refs
+            .update_ref_file(&path, &self.oid(name))
+            .unwrap();
+
+        self
+    }
+
+    pub fn oid(&self, name: &str) -> String {
+        match self.oids.get(name) {
+            Some(oid) => oid.clone(),
+            None => panic!("no commit named {}", name),
+        }
+    }
+
+    /// Read a commit back from the object database
+    pub fn load_commit(&mut self, oid: &str) -> Commit {
+        match self.repo.database.load(oid) {
+            ParsedObject::Commit(commit) => commit.clone(),
+            _ => panic!("{} is not a commit", oid),
+        }
+    }
+}
@@ -7,12 +7,13 @@ use crate::commands::CommandContext;
 
 const DEFAULT_BRANCH: &str = "master";
 
-pub fn init_command<I, O, E>(ctx: CommandContext<I, O, E>) -> Result<(), String>
+pub fn init_command<I, O, E>(mut ctx: CommandContext<I, O, E>) -> Result<(), String>
 where
     I: Read,
     O: Write,
     E: Write,
 {
+    let git_dir = ctx.env.get("GIT_DIR").map(|git_dir| ctx.dir.join(git_dir));
     let working_dir = ctx.dir;
     let options = ctx.options.as_ref().unwrap();
     let args: Vec<_> = if let Some(args) = options.values_of("args") {
@@ -25,7 +26,7 @@ where
     } else {
         working_dir.as_path()
     };
-    let git_path = root_path.join(".git");
+    let git_path = git_dir.unwrap_or_else(|| root_path.join(".git"));
 
     for d in ["objects", "refs/heads"].iter() {
         fs::create_dir_all(git_path.join(d)).expect("failed to create dir");
@@ -39,6 +40,11 @@ where
     ))
     .map_err(|e| e.to_string())?;
 
-    println!("Initialized empty Jit repository in {:?}\n", git_path);
+    writeln!(
+        ctx.stdout,
+        "Initialized empty Jit repository in {:?}\n",
+        git_path
+    )
+    .map_err(|e| e.to_string())?;
     Ok(())
 }
@@ -1,9 +1,9 @@
-use crate::commands::CommandContext;
+use crate::commands::{paint, CommandContext};
 use crate::database::commit::Commit;
 use crate::database::object::Object;
-use crate::database::ParsedObject;
 use crate::pager::Pager;
 use crate::repository::Repository;
+use crate::rev_list::RevList;
 use colored::*;
 use std::io::{Read, Write};
 
@@ -13,9 +13,8 @@ where
     O: Write,
     E: Write,
 {
-    // repo: Repository,
+    repo: Repository,
     ctx: CommandContext<'a, I, O, E>,
-    commits: CommitsLog,
 }
 
 impl<'a, I, O, E> Log<'a, I, O, E>
@@ -25,21 +24,23 @@ where
     E: Write,
 {
     pub fn new(ctx: CommandContext<'a, I, O, E>) -> Log<'a, I, O, E> {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
-        let current_oid = repo.refs.read_head();
-        let commits = CommitsLog::new(current_oid, repo);
+        let repo = ctx.repo();
 
-        Log { ctx, commits }
+        Log { repo, ctx }
     }
 
     pub fn run(&mut self) -> Result<(), String> {
-        Pager::setup_pager();
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        let args: Vec<_> = if let Some(args) = options.values_of("args") {
+            args.collect()
+        } else {
+            vec![]
+        };
+
+        let commits = RevList::new(&mut self.repo, &args)?.collect::<Result<Vec<_>, _>>()?;
 
-        let mut commits = vec![];
-        for c in &mut self.commits {
-            commits.push(c);
+        if self.ctx.use_pager {
+            Pager::setup_pager(self.ctx.env);
         }
 
         for c in commits {
@@ -49,45 +50,165 @@ where
         Ok(())
     }
 
-    fn show_commit(&self, commit: &Commit) -> Result<(), String> {
+    fn show_commit(&mut self, commit: &Commit) -> Result<(), String> {
+        if self
+            .ctx
+            .options
+            .as_ref()
+            .map(|o| o.is_present("oneline"))
+            .unwrap_or(false)
+        {
+            return self.show_commit_oneline(commit);
+        }
+
         let author = &commit.author;
-        println!();
-        println!("commit {}", commit.get_oid().yellow());
-        println!("Author: {} <{}>", author.name, author.email);
-        println!("Date: {}", author.readable_time());
-        println!();
+        writeln!(self.ctx.stdout).map_err(|e| e.to_string())?;
+        writeln!(
+            self.ctx.stdout,
+            "commit {}",
+            paint(self.ctx.use_color, commit.get_oid().yellow())
+        )
+        .map_err(|e| e.to_string())?;
+        writeln!(
+            self.ctx.stdout,
+            "Author: {} <{}>",
+            author.name, author.email
+        )
+        .map_err(|e| e.to_string())?;
+        writeln!(self.ctx.stdout, "Date: {}", author.readable_time()).map_err(|e| e.to_string())?;
+        writeln!(self.ctx.stdout).map_err(|e| e.to_string())?;
 
         for line in commit.message.lines() {
-            println!("    {}", line);
+            writeln!(self.ctx.stdout, "    {}", line).map_err(|e| e.to_string())?;
         }
         Ok(())
     }
-}
 
-struct CommitsLog {
-    current_oid: Option<String>,
-    repo: Repository,
+    fn show_commit_oneline(&mut self, commit: &Commit) -> Result<(), String> {
+        let abbrev = self.repo.database.abbrev(&commit.get_oid());
+        writeln!(
+            self.ctx.stdout,
+            "{} {}",
+            paint(self.ctx.use_color, abbrev.yellow()),
+            commit.title_line()
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
 }
 
-impl CommitsLog {
-    pub fn new(current_oid: Option<String>, repo: Repository) -> CommitsLog {
-        CommitsLog { current_oid, repo }
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::store::{LooseStore, ObjectStore};
+
+    fn commit_file(cmd_helper: &mut CommandHelper, contents: &str, message: &str) -> String {
+        cmd_helper.write_file("file.txt", contents.as_bytes()).unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit(message);
+        cmd_helper
+            .read_file(".git/refs/heads/master")
+            .unwrap()
+            .trim()
+            .to_string()
     }
-}
 
-impl Iterator for CommitsLog {
-    type Item = Commit;
-
-    fn next(&mut self) -> Option<Commit> {
-        if let Some(current_oid) = &self.current_oid {
-            if let ParsedObject::Commit(commit) = self.repo.database.load(&current_oid) {
-                self.current_oid = commit.parent.clone();
-                Some(commit.clone())
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.set_env("GIT_PAGER", "cat");
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+    }
+
+    #[test]
+    fn prints_abbreviated_oids_with_oneline() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let first = commit_file(&mut cmd_helper, "one", "first");
+        let second = commit_file(&mut cmd_helper, "two", "second");
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["log", "--oneline"]).unwrap();
+        assert_output(
+            &stdout,
+            &format!("{} second\n{} first\n", &second[..7], &first[..7]),
+        );
+    }
+
+    #[test]
+    fn extends_abbreviation_until_unique() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let oid = commit_file(&mut cmd_helper, "one", "first");
+
+        // An object sharing the first eight characters forces a
+        // nine-character abbreviation
+        let other = format!("{}{}", &oid[..8], if &oid[8..9] == "0" { "1" } else { "0" });
+        cmd_helper
+            .write_file(
+                &format!(".git/objects/{}/{}{}", &other[..2], &other[2..], &oid[9..]),
+                b"",
+            )
+            .unwrap();
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["log", "--oneline"]).unwrap();
+        assert_output(&stdout, &format!("{} first\n", &oid[..9]));
+    }
+
+    fn merge_graph(cmd_helper: &CommandHelper) -> GraphBuilder {
+        let mut graph = GraphBuilder::new(cmd_helper);
+        graph
+            .commit("A", &[])
+            .commit("B", &["A"])
+            .commit("C", &["A"])
+            .commit("D", &["B", "C"])
+            .branch("master", "D");
+        graph
+    }
+
+    fn oneline(graph: &GraphBuilder, names: &[&str]) -> String {
+        names
+            .iter()
+            .map(|name| format!("{} {}\n", &graph.oid(name)[..7], name))
+            .collect()
+    }
+
+    #[test]
+    fn shows_merged_history_newest_first() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let graph = merge_graph(&cmd_helper);
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["log", "--oneline"]).unwrap();
+        assert_output(&stdout, &oneline(&graph, &["D", "C", "B", "A"]));
+    }
+
+    #[test]
+    fn follows_the_second_parent_of_a_merge() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let graph = merge_graph(&cmd_helper);
+
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["log", "--oneline", "master^2"])
+            .unwrap();
+        assert_output(&stdout, &oneline(&graph, &["C", "A"]));
+    }
+
+    #[test]
+    fn fails_for_malformed_commits() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let oid = "1".repeat(40);
+        let objects = cmd_helper.repo().root_path.join(".git/objects");
+        LooseStore::new(&objects)
+            .write(&oid, b"commit 16\0parent none\n\nfirst\n")
+            .unwrap();
+        cmd_helper
+            .write_file(".git/refs/heads/master", oid.as_bytes())
+            .unwrap();
+
+        assert_output(
+            &cmd_helper.jit_cmd(&["log"]).unwrap_err(),
+            &format!("fatal: bad object {}: no tree header\n", oid),
+        );
     }
 }
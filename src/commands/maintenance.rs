@@ -0,0 +1,249 @@
+use crate::commands::CommandContext;
+use crate::database::commit_graph::CommitGraph;
+use crate::repository::Repository;
+use crate::rev_list::RevList;
+use std::io::{Read, Write};
+
+/// Tasks run by `maintenance run`, in their default order
+const TASKS: [&str; 3] = ["loose-objects", "pack-refs", "commit-graph"];
+
+pub struct Maintenance<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> Maintenance<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Maintenance<'a, I, O, E> {
+        let repo = ctx.repo();
+
+        Maintenance { repo, ctx }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        let run_options = match options.subcommand() {
+            ("run", Some(run_options)) => run_options,
+            _ => return Err("usage: rug maintenance run [--task=<task>]\n".to_string()),
+        };
+
+        let tasks: Vec<_> = match run_options.values_of("task") {
+            Some(tasks) => tasks.collect(),
+            None => TASKS.to_vec(),
+        };
+        if let Some(task) = tasks.iter().find(|task| !TASKS.contains(task)) {
+            return Err(format!("error: '{}' is not a valid task\n", task));
+        }
+
+        for task in tasks {
+            match task {
+                "loose-objects" => self.loose_objects()?,
+                "pack-refs" => self.pack_refs()?,
+                "commit-graph" => self.commit_graph()?,
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drop loose objects that are already stored in a pack. Packing
+    /// the remaining loose objects is left to `git`.
+    fn loose_objects(&mut self) -> Result<(), String> {
+        self.repo
+            .database
+            .prune_packed()
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn pack_refs(&mut self) -> Result<(), String> {
+        self.repo
+            .refs
+            .pack_refs(&mut self.repo.database, true)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Write a commit-graph covering everything reachable from HEAD
+    /// and all refs
+    fn commit_graph(&mut self) -> Result<(), String> {
+        let refs = &self.repo.refs;
+        let mut oids: Vec<String> = refs.read_head().into_iter().collect();
+        oids.extend(
+            refs.list_all_refs()
+                .map_err(|e| e.to_string())?
+                .iter()
+                .filter_map(|r#ref| refs.read_oid(r#ref)),
+        );
+
+        let mut graph = CommitGraph::new();
+        for commit in RevList::from_oids(&mut self.repo, &oids)? {
+            graph.add(&commit?);
+        }
+
+        graph
+            .write(&self.repo.database.commit_graph_path())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::blob::Blob;
+    use crate::database::object::Object;
+    use crate::database::pack_index::tests::v2_index;
+    use crate::database::ParsedObject;
+
+    /// Builds a merge on master and returns its ID
+    fn before(cmd_helper: &mut CommandHelper) -> String {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        let mut graph = GraphBuilder::new(cmd_helper);
+        graph
+            .commit("A", &[])
+            .commit("B", &["A"])
+            .commit("C", &["A"])
+            .commit("D", &["B", "C"])
+            .branch("master", "D");
+
+        graph.oid("D")
+    }
+
+    fn object_path(oid: &str) -> String {
+        format!(".git/objects/{}/{}", &oid[0..2], &oid[2..])
+    }
+
+    #[test]
+    fn prunes_loose_objects_stored_in_packs() {
+        let mut cmd_helper = CommandHelper::new();
+        let oid = before(&mut cmd_helper);
+
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.pack", b"PACK")
+            .unwrap();
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.idx", &v2_index(&[&oid]))
+            .unwrap();
+
+        cmd_helper
+            .jit_cmd(&["maintenance", "run", "--task", "loose-objects"])
+            .unwrap();
+
+        assert!(cmd_helper.read_bytes(&object_path(&oid)).is_err());
+        let (stdout, _) = cmd_helper.jit_cmd(&["count-objects"]).unwrap();
+        assert!(stdout.starts_with("11 objects, "));
+    }
+
+    #[test]
+    fn runs_every_task_after_pruning_packed_objects() {
+        let mut cmd_helper = CommandHelper::new();
+        let oid = before(&mut cmd_helper);
+        let blob = Blob::new(b"B").get_oid();
+
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.pack", b"PACK")
+            .unwrap();
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.idx", &v2_index(&[&blob]))
+            .unwrap();
+
+        cmd_helper.jit_cmd(&["maintenance", "run"]).unwrap();
+
+        assert!(cmd_helper.read_bytes(&object_path(&blob)).is_err());
+        assert!(cmd_helper.read_bytes(&object_path(&oid)).is_ok());
+        assert!(cmd_helper.read_file(".git/refs/heads/master").is_err());
+        assert!(cmd_helper
+            .read_bytes(".git/objects/info/commit-graph")
+            .is_ok());
+    }
+
+    #[test]
+    fn fails_for_commits_only_stored_in_packs() {
+        let mut cmd_helper = CommandHelper::new();
+        let oid = before(&mut cmd_helper);
+        let parent = match cmd_helper.repo().database.load(&oid) {
+            ParsedObject::Commit(commit) => commit.parents[0].clone(),
+            _ => unreachable!(),
+        };
+
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.pack", b"PACK")
+            .unwrap();
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.idx", &v2_index(&[&parent]))
+            .unwrap();
+        cmd_helper.delete(&object_path(&parent)).unwrap();
+
+        assert_output(
+            &cmd_helper.jit_cmd(&["maintenance", "run"]).unwrap_err(),
+            &format!(
+                "fatal: object {} is packed; reading packs is not supported\n",
+                parent
+            ),
+        );
+
+        assert!(cmd_helper.read_bytes(&object_path(&oid)).is_ok());
+        assert!(cmd_helper
+            .read_bytes(".git/objects/info/commit-graph")
+            .is_err());
+    }
+
+    #[test]
+    fn packs_refs() {
+        let mut cmd_helper = CommandHelper::new();
+        let oid = before(&mut cmd_helper);
+        cmd_helper.jit_cmd(&["branch", "topic", "@^"]).unwrap();
+
+        cmd_helper
+            .jit_cmd(&["maintenance", "run", "--task=pack-refs"])
+            .unwrap();
+
+        assert!(cmd_helper.read_file(".git/refs/heads/master").is_err());
+        let packed_refs = cmd_helper.read_file(".git/packed-refs").unwrap();
+        assert!(packed_refs.contains(&format!("{} refs/heads/master\n", oid)));
+    }
+
+    #[test]
+    fn writes_a_commit_graph() {
+        let mut cmd_helper = CommandHelper::new();
+        let oid = before(&mut cmd_helper);
+
+        cmd_helper.jit_cmd(&["maintenance", "run"]).unwrap();
+
+        let graph = cmd_helper
+            .read_bytes(".git/objects/info/commit-graph")
+            .unwrap();
+        assert_eq!(b"CGPH\x01\x01\x03\x00", &graph[0..8]);
+        assert_eq!(b"OIDF", &graph[8..12]);
+
+        // The fan-out table follows the four-entry chunk lookup, and
+        // its last slot holds the number of commits
+        let commit_count = &graph[8 + 4 * 12 + 255 * 4..8 + 4 * 12 + 256 * 4];
+        assert_eq!(&4u32.to_be_bytes(), commit_count);
+
+        assert!(cmd_helper.read_bytes(&object_path(&oid)).is_ok());
+        assert!(cmd_helper.read_file(".git/refs/heads/master").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_tasks() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["maintenance", "run", "--task", "gc"])
+                .unwrap_err(),
+            "error: 'gc' is not a valid task\n",
+        );
+    }
+}
@@ -1,6 +1,12 @@
+use crate::repository::Repository;
 use clap::{App, Arg, ArgMatches, SubCommand};
+use colored::ColoredString;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::io::{Read, Write};
+use std::iter;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
 
 mod add;
@@ -19,6 +25,25 @@ mod checkout;
 use checkout::Checkout;
 mod log;
 use log::Log;
+mod pack_refs;
+use pack_refs::PackRefs;
+mod mv;
+use mv::Mv;
+mod shortlog;
+use shortlog::Shortlog;
+mod count_objects;
+use count_objects::CountObjects;
+mod maintenance;
+use maintenance::Maintenance;
+mod prune;
+use prune::Prune;
+mod reflog;
+use reflog::Reflog;
+mod update_index;
+use update_index::UpdateIndex;
+
+#[cfg(test)]
+mod fixture;
 
 #[derive(Debug)]
 pub struct CommandContext<'a, I, O, E>
@@ -33,10 +58,62 @@ where
     pub stdin: I,
     pub stdout: O,
     pub stderr: E,
+    // Only the process's own stdout can be handed to a pager, which
+    // takes it over by forking
+    pub use_pager: bool,
+    // Whether output may be coloured at all; `colored` still leaves it
+    // plain when the environment asks for that
+    pub use_color: bool,
+}
+
+impl<'a, I, O, E> CommandContext<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    /// `$GIT_DIR`, relative to `dir`, or else `.git` in `dir`
+    pub fn git_dir(&self) -> PathBuf {
+        match self.env.get("GIT_DIR") {
+            Some(git_dir) => self.dir.join(git_dir),
+            None => self.dir.join(".git"),
+        }
+    }
+
+    /// The repository in `git_dir`, with `dir` as its working tree
+    pub fn repo(&self) -> Repository {
+        Repository::with_git_dir(&self.dir, &self.git_dir())
+    }
+}
+
+/// `text` as styled, or as plain text when the context's `use_color`
+/// is off. Styling is left to each call rather than to
+/// `colored::control`, whose override applies to the whole process.
+pub fn paint(use_color: bool, text: ColoredString) -> String {
+    if use_color {
+        text.to_string()
+    } else {
+        text.deref().to_string()
+    }
+}
+
+/// What a command run by `run_command` printed, and the status it
+/// would have exited the process with
+#[derive(Debug)]
+pub struct Outcome {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: i32,
 }
 
 pub fn get_app() -> App<'static, 'static> {
     App::new("rug")
+        .arg(
+            Arg::with_name("git_dir")
+                .long("git-dir")
+                .value_name("path")
+                .takes_value(true),
+        )
         .subcommand(
             SubCommand::with_name("init")
                 .about("Create an empty Git repository or reinitialize an existing one")
@@ -76,17 +153,125 @@ pub fn get_app() -> App<'static, 'static> {
         .subcommand(
             SubCommand::with_name("checkout")
                 .about("Switch branches or restore working tree files")
+                .arg(Arg::with_name("force").short("f").long("force"))
                 .arg(Arg::with_name("args").multiple(true)),
         )
         .subcommand(
             SubCommand::with_name("log")
                 .about("Show commit logs")
+                .arg(Arg::with_name("oneline").long("oneline"))
+                .arg(Arg::with_name("args").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("pack-refs")
+                .about("Pack heads and tags for efficient repository access")
+                .arg(Arg::with_name("all").long("all")),
+        )
+        .subcommand(
+            SubCommand::with_name("mv")
+                .about("Move or rename a file, a directory, or a symlink")
+                .arg(Arg::with_name("dry_run").short("n").long("dry-run"))
+                .arg(Arg::with_name("force").short("f").long("force"))
+                .arg(Arg::with_name("args").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("shortlog")
+                .about("Summarize 'git log' output")
+                .arg(Arg::with_name("summary").short("s").long("summary"))
+                .arg(Arg::with_name("numbered").short("n").long("numbered"))
+                .arg(Arg::with_name("email").short("e").long("email"))
+                .arg(Arg::with_name("args").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("count-objects")
+                .about("Count unpacked number of objects and their disk consumption")
+                .arg(Arg::with_name("verbose").short("v").long("verbose")),
+        )
+        .subcommand(
+            SubCommand::with_name("maintenance")
+                .about("Run tasks to optimize Git repository data")
+                .subcommand(
+                    SubCommand::with_name("run")
+                        .about("Run loose-objects, pack-refs and commit-graph, or the given tasks")
+                        .arg(
+                            Arg::with_name("task")
+                                .long("task")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("prune")
+                .about("Prune all unreachable objects from the object database")
+                .arg(Arg::with_name("dry_run").short("n").long("dry-run"))
+                .arg(Arg::with_name("verbose").short("v").long("verbose"))
+                .arg(Arg::with_name("expire").long("expire").takes_value(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("reflog")
+                .about("Manage reflog information")
+                .subcommand(
+                    SubCommand::with_name("expire")
+                        .about("Prune older reflog entries")
+                        .arg(Arg::with_name("dry_run").short("n").long("dry-run"))
+                        .arg(Arg::with_name("all").long("all"))
+                        .arg(Arg::with_name("expire").long("expire").takes_value(true))
+                        .arg(
+                            Arg::with_name("expire_unreachable")
+                                .long("expire-unreachable")
+                                .takes_value(true),
+                        )
+                        .arg(Arg::with_name("args").multiple(true)),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("update-index")
+                .about("Register file contents in the working tree to the index")
+                .arg(Arg::with_name("assume_unchanged").long("assume-unchanged"))
+                .arg(Arg::with_name("no_assume_unchanged").long("no-assume-unchanged"))
+                .arg(Arg::with_name("skip_worktree").long("skip-worktree"))
+                .arg(Arg::with_name("no_skip_worktree").long("no-skip-worktree"))
                 .arg(Arg::with_name("args").multiple(true)),
         )
 }
 
 pub fn execute<'a, I, O, E>(
     matches: ArgMatches<'a>,
+    ctx: CommandContext<'a, I, O, E>,
+) -> Result<(), String>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    // As in git, `--git-dir` sets `GIT_DIR` for the command
+    let env = match matches.value_of("git_dir") {
+        Some(git_dir) => {
+            let mut env = ctx.env.clone();
+            env.insert("GIT_DIR".to_string(), git_dir.to_string());
+            Cow::Owned(env)
+        }
+        None => Cow::Borrowed(ctx.env),
+    };
+    let ctx = CommandContext {
+        dir: ctx.dir,
+        env: &env,
+        options: None,
+        stdin: ctx.stdin,
+        stdout: ctx.stdout,
+        stderr: ctx.stderr,
+        use_pager: ctx.use_pager,
+        use_color: ctx.use_color,
+    };
+
+    Repository::check_config(&ctx.git_dir())?;
+    run_subcommand(&matches, ctx)
+}
+
+fn run_subcommand<'a, I, O, E>(
+    matches: &ArgMatches<'a>,
     mut ctx: CommandContext<'a, I, O, E>,
 ) -> Result<(), String>
 where
@@ -132,16 +317,151 @@ where
             let mut cmd = Log::new(ctx);
             cmd.run()
         }
+        ("pack-refs", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = PackRefs::new(ctx);
+            cmd.run()
+        }
+        ("mv", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = Mv::new(ctx);
+            cmd.run()
+        }
+        ("shortlog", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = Shortlog::new(ctx);
+            cmd.run()
+        }
+        ("count-objects", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = CountObjects::new(ctx);
+            cmd.run()
+        }
+        ("maintenance", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = Maintenance::new(ctx);
+            cmd.run()
+        }
+        ("prune", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = Prune::new(ctx);
+            cmd.run()
+        }
+        ("reflog", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = Reflog::new(ctx);
+            cmd.run()
+        }
+        ("update-index", sub_matches) => {
+            ctx.options = sub_matches.cloned();
+            let mut cmd = UpdateIndex::new(ctx);
+            cmd.run()
+        }
         _ => Ok(()),
     }
 }
 
+/// Run the command line `args`, without the program name, writing its
+/// output to `ctx.stdout` and `ctx.stderr`. Returns the status the
+/// process should exit with: 1 for a usage error, 128 for a fatal
+/// error, and 101 for a command that panics.
+pub fn run<I, O, E>(args: &[&str], ctx: CommandContext<I, O, E>) -> i32
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    let CommandContext {
+        dir,
+        env,
+        stdin,
+        mut stdout,
+        mut stderr,
+        use_pager,
+        use_color,
+        ..
+    } = ctx;
+
+    let args = iter::once("rug").chain(args.iter().cloned());
+    match get_app().get_matches_from_safe(args) {
+        Err(e) if e.use_stderr() => {
+            let _ = writeln!(stderr, "{}", e.message);
+            1
+        }
+        // `--help` and `--version`, which clap also reports as errors
+        Err(e) => {
+            let _ = writeln!(stdout, "{}", e.message);
+            0
+        }
+        Ok(matches) => {
+            let ctx = CommandContext {
+                dir,
+                env,
+                options: None,
+                stdin,
+                stdout: &mut stdout,
+                stderr: &mut stderr,
+                use_pager,
+                use_color,
+            };
+
+            match panic::catch_unwind(AssertUnwindSafe(|| execute(matches, ctx))) {
+                Ok(Ok(())) => 0,
+                Ok(Err(msg)) => {
+                    let _ = stderr.write_all(msg.as_bytes());
+                    128
+                }
+                Err(_) => 101,
+            }
+        }
+    }
+}
+
+/// Run the command line `args`, without the program name, inside the
+/// current process. Output is appended to `ctx.stdout` and
+/// `ctx.stderr` and returned once the command finishes, along with
+/// the status `run` gives. It is never paged or coloured.
+pub fn run_command<I>(args: &[&str], ctx: CommandContext<I, Vec<u8>, Vec<u8>>) -> Outcome
+where
+    I: Read,
+{
+    let CommandContext {
+        dir,
+        env,
+        stdin,
+        mut stdout,
+        mut stderr,
+        ..
+    } = ctx;
+
+    let status = run(
+        args,
+        CommandContext {
+            dir,
+            env,
+            options: None,
+            stdin,
+            stdout: &mut stdout,
+            stderr: &mut stderr,
+            use_pager: false,
+            use_color: false,
+        },
+    );
+
+    Outcome {
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        status,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    pub use crate::commands::fixture::GraphBuilder;
     use crate::repository::Repository;
     use crate::util::*;
-    use assert_cmd::prelude::*;
+    use colored::Colorize;
     use filetime::FileTime;
     use std::env;
     use std::fs::{self, File, OpenOptions};
@@ -149,10 +469,8 @@ mod tests {
     use std::io::Write;
     use std::os::unix::fs::PermissionsExt;
     use std::path::Path;
-    use std::process::{Command, Stdio};
     use std::str;
     use std::time::{SystemTime, UNIX_EPOCH};
-    extern crate assert_cmd;
 
     pub fn gen_repo_path() -> PathBuf {
         let mut temp_dir = generate_temp_name();
@@ -186,7 +504,20 @@ mod tests {
             }
         }
 
-        fn set_env(&mut self, key: &str, value: &str) {
+        /// The repository commands run against, which follows
+        /// `GIT_DIR` as they do
+        pub fn repo(&self) -> Repository {
+            Repository::with_git_dir(&self.repo_path, &self.git_path())
+        }
+
+        pub fn git_path(&self) -> PathBuf {
+            match self.env.get("GIT_DIR") {
+                Some(git_dir) => self.repo_path.join(git_dir),
+                None => self.repo_path.join(".git"),
+            }
+        }
+
+        pub fn set_env(&mut self, key: &str, value: &str) {
             self.env.insert(key.to_string(), value.to_string());
         }
 
@@ -195,40 +526,32 @@ mod tests {
         }
 
         pub fn jit_cmd(&mut self, args: &[&str]) -> Result<(String, String), String> {
-            let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))
-                .unwrap()
-                .args(args)
-                .current_dir(&self.repo_path)
-                .envs(&self.env)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .stdin(Stdio::piped())
-                .spawn()
-                .expect("Failed to spawn child process");
-
-            cmd.stdin
-                .as_mut()
-                .unwrap()
-                .write_all(self.stdin.as_bytes())
-                .unwrap();
-
-            let output = cmd.wait_with_output().expect("failed to run executable");
-
-            let (stdout, stderr) = (
-                String::from_utf8_lossy(&output.stdout).to_string(),
-                String::from_utf8_lossy(&output.stderr).to_string(),
-            );
-
-            if output.status.success() {
-                Ok((stdout, stderr))
+            let ctx = CommandContext {
+                dir: self.repo_path.clone(),
+                env: &self.env,
+                options: None,
+                stdin: self.stdin.as_bytes(),
+                stdout: vec![],
+                stderr: vec![],
+                use_pager: false,
+                use_color: false,
+            };
+            let outcome = run_command(args, ctx);
+
+            if outcome.status == 0 {
+                Ok((outcome.stdout, outcome.stderr))
             } else {
-                Err(stderr)
+                Err(outcome.stderr)
             }
         }
 
         pub fn commit(&mut self, msg: &str) {
-            self.set_env("GIT_AUTHOR_NAME", "A. U. Thor");
-            self.set_env("GIT_AUTHOR_EMAIL", "author@example.com");
+            self.commit_as("A. U. Thor", "author@example.com", msg);
+        }
+
+        pub fn commit_as(&mut self, name: &str, email: &str, msg: &str) {
+            self.set_env("GIT_AUTHOR_NAME", name);
+            self.set_env("GIT_AUTHOR_EMAIL", email);
             self.set_stdin(msg);
             self.jit_cmd(&["commit"]).unwrap();
         }
@@ -247,6 +570,14 @@ mod tests {
             Ok(())
         }
 
+        pub fn read_file(&self, file_name: &str) -> Result<String, std::io::Error> {
+            fs::read_to_string(self.repo_path.join(file_name))
+        }
+
+        pub fn read_bytes(&self, file_name: &str) -> Result<Vec<u8>, std::io::Error> {
+            fs::read(self.repo_path.join(file_name))
+        }
+
         pub fn mkdir(&self, dir_name: &str) -> Result<(), std::io::Error> {
             fs::create_dir_all(self.repo_path.join(dir_name))
         }
@@ -348,6 +679,93 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reports_failures_with_git_exit_statuses() {
+        let cmd_helper = CommandHelper::new();
+        let run = |args: &[&str]| {
+            run_command(
+                args,
+                CommandContext {
+                    dir: cmd_helper.repo_path.clone(),
+                    env: &cmd_helper.env,
+                    options: None,
+                    stdin: &b""[..],
+                    stdout: vec![],
+                    stderr: vec![],
+                    use_pager: false,
+                    use_color: false,
+                },
+            )
+        };
+
+        let outcome = run(&["init"]);
+        assert_eq!(0, outcome.status);
+        assert!(outcome.stdout.starts_with("Initialized empty Jit repository"));
+
+        let outcome = run(&["update-index", "--skip-worktree", "missing.txt"]);
+        assert_eq!(128, outcome.status);
+        assert_output(&outcome.stderr, "fatal: Unable to mark file missing.txt\n");
+
+        let outcome = run(&["commit"]);
+        assert_eq!(128, outcome.status);
+        assert_output(&outcome.stderr, "fatal: GIT_AUTHOR_NAME not set\n");
+
+        let outcome = run(&["no-such-command"]);
+        assert_eq!(1, outcome.status);
+        assert!(outcome.stderr.contains("no-such-command"));
+        assert_eq!("", outcome.stdout);
+    }
+
+    #[test]
+    fn uses_the_git_dir_from_the_environment_or_option() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.set_env("GIT_DIR", "repo.git");
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        assert!(cmd_helper.read_file("repo.git/HEAD").is_ok());
+        assert!(cmd_helper.read_file(".git/HEAD").is_err());
+        let oid = cmd_helper.read_file("repo.git/refs/heads/master").unwrap();
+
+        cmd_helper.env.remove("GIT_DIR");
+        let (stdout, _) = cmd_helper.jit_cmd(&["log", "--oneline"]).unwrap();
+        assert_output(&stdout, "");
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["--git-dir", "repo.git", "log", "--oneline"])
+            .unwrap();
+        assert_output(&stdout, &format!("{} first\n", &oid[0..7]));
+    }
+
+    #[test]
+    fn leaves_output_plain_without_colour() {
+        assert_eq!("master", paint(false, "master".green()));
+
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["branch"]).unwrap();
+        assert_output(&stdout, "* master\n");
+    }
+
+    #[test]
+    fn rejects_an_invalid_object_cache_limit() {
+        let mut cmd_helper = CommandHelper::new();
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper
+            .write_file(".git/config", b"[core]\n\tobjectCacheLimit = lots\n")
+            .unwrap();
+
+        assert_output(
+            &cmd_helper.jit_cmd(&["count-objects"]).unwrap_err(),
+            "fatal: bad numeric config value 'lots' for 'core.objectcachelimit': invalid unit\n",
+        );
+    }
+
     pub fn assert_output(stream: &str, expected: &str) {
         assert_eq!(stream, expected);
     }
@@ -0,0 +1,410 @@
+use crate::commands::CommandContext;
+use crate::repository::Repository;
+use crate::util::normalize_path;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+pub struct Mv<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> Mv<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Mv<'a, I, O, E> {
+        let repo = ctx.repo();
+
+        Mv { repo, ctx }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        let args: Vec<_> = if let Some(args) = options.values_of("args") {
+            args.map(normalize_path).collect()
+        } else {
+            vec![]
+        };
+        let dry_run = options.is_present("dry_run");
+        let force = options.is_present("force");
+
+        if args.len() < 2 {
+            return Err("usage: rug mv [<options>] <source>... <destination>\n".to_string());
+        }
+
+        self.repo
+            .index
+            .load_for_update()
+            .map_err(|e| e.to_string())?;
+
+        let renames = match self.plan_renames(&args, force) {
+            Ok(renames) => renames,
+            Err(e) => {
+                self.repo.index.release_lock().map_err(|e| e.to_string())?;
+                return Err(e);
+            }
+        };
+
+        if dry_run {
+            for (source, destination) in &renames {
+                writeln!(self.ctx.stdout, "Renaming {} to {}", source, destination)
+                    .map_err(|e| e.to_string())?;
+            }
+            return self.repo.index.release_lock().map_err(|e| e.to_string());
+        }
+
+        if let Err(e) = self.rename_all(&renames) {
+            self.repo.index.release_lock().map_err(|e| e.to_string())?;
+            return Err(e);
+        }
+        for (source, destination) in &renames {
+            self.repo.index.rename(source, destination);
+        }
+
+        self.repo.index.write_updates().map_err(|e| e.to_string())
+    }
+
+    /// Rename every pair in the workspace. If one fails, those already
+    /// done are moved back, so that the workspace still matches the
+    /// index.
+    fn rename_all(&self, renames: &[(String, String)]) -> Result<(), String> {
+        let workspace = &self.repo.workspace;
+
+        for (done, (source, destination)) in renames.iter().enumerate() {
+            if let Err(e) = fs::rename(workspace.abs_path(source), workspace.abs_path(destination))
+            {
+                for (source, destination) in renames[..done].iter().rev() {
+                    fs::rename(workspace.abs_path(destination), workspace.abs_path(source))
+                        .map_err(|e| format!("fatal: restoring '{}' failed: {}\n", source, e))?;
+                }
+                return Err(format!("fatal: renaming '{}' failed: {}\n", source, e));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pair each source with its destination, checking every rename
+    /// before anything on disk or in the index is touched
+    fn plan_renames(&self, args: &[String], force: bool) -> Result<Vec<(String, String)>, String> {
+        let (destination, sources) = args.split_last().unwrap();
+        let into_dir = self.repo.workspace.is_dir(destination);
+
+        if sources.len() > 1 && !into_dir {
+            return Err(format!(
+                "fatal: destination '{}' is not a directory\n",
+                destination
+            ));
+        }
+
+        let mut renames = vec![];
+        let mut targets = HashSet::new();
+
+        for source in sources {
+            let target = if into_dir {
+                let name = Path::new(source)
+                    .file_name()
+                    .ok_or_else(|| rename_error("bad source", source, destination))?;
+                Path::new(destination)
+                    .join(name)
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            } else {
+                destination.clone()
+            };
+
+            self.check_rename(source, &target, force)?;
+
+            if !targets.insert(target.clone()) {
+                return Err(rename_error(
+                    "multiple sources for the same target",
+                    source,
+                    &target,
+                ));
+            }
+            renames.push((source.clone(), target));
+        }
+
+        Ok(renames)
+    }
+
+    fn check_rename(&self, source: &str, target: &str, force: bool) -> Result<(), String> {
+        let workspace = &self.repo.workspace;
+
+        let source_stat = workspace
+            .stat_file(source)
+            .map_err(|_| rename_error("bad source", source, target))?;
+
+        if !self.repo.index.is_tracked(source) {
+            return Err(rename_error("not under version control", source, target));
+        }
+
+        if source == target {
+            return Err(rename_error(
+                "source and destination are the same",
+                source,
+                target,
+            ));
+        }
+
+        if Path::new(target).starts_with(source) {
+            return Err(rename_error(
+                "can not move directory into itself",
+                source,
+                target,
+            ));
+        }
+
+        if let Ok(target_stat) = workspace.stat_file(target) {
+            // Only a file may replace a file, and only when forced
+            if !force || source_stat.is_dir() || target_stat.is_dir() {
+                return Err(rename_error("destination exists", source, target));
+            }
+        }
+
+        let parent = Path::new(target).parent().unwrap();
+        if !workspace.abs_path(parent.to_str().unwrap()).is_dir() {
+            return Err(rename_error(
+                "destination directory does not exist",
+                source,
+                target,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn rename_error(message: &str, source: &str, destination: &str) -> String {
+    format!(
+        "fatal: {}, source={}, destination={}\n",
+        message, source, destination
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use std::collections::HashMap;
+    use std::os::unix::fs::symlink;
+
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("1.txt", b"1").unwrap();
+        cmd_helper.write_file("outer/2.txt", b"2").unwrap();
+        cmd_helper.write_file("outer/inner/3.txt", b"3").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+    }
+
+    #[test]
+    fn renames_a_tracked_file() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper.jit_cmd(&["mv", "1.txt", "one.txt"]).unwrap();
+
+        let mut workspace = HashMap::new();
+        workspace.insert("one.txt", "1");
+        workspace.insert("outer/2.txt", "2");
+        workspace.insert("outer/inner/3.txt", "3");
+        cmd_helper.assert_workspace(workspace);
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, "one.txt".to_string()),
+                (0o100644, "outer/2.txt".to_string()),
+                (0o100644, "outer/inner/3.txt".to_string()),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    fn renames_a_directory() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper.jit_cmd(&["mv", "outer/", "nested"]).unwrap();
+
+        let mut workspace = HashMap::new();
+        workspace.insert("1.txt", "1");
+        workspace.insert("nested/2.txt", "2");
+        workspace.insert("nested/inner/3.txt", "3");
+        cmd_helper.assert_workspace(workspace);
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, "1.txt".to_string()),
+                (0o100644, "nested/2.txt".to_string()),
+                (0o100644, "nested/inner/3.txt".to_string()),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    fn moves_several_sources_into_a_directory() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper
+            .jit_cmd(&["mv", "1.txt", "outer/2.txt", "outer/inner"])
+            .unwrap();
+
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, "outer/inner/1.txt".to_string()),
+                (0o100644, "outer/inner/2.txt".to_string()),
+                (0o100644, "outer/inner/3.txt".to_string()),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    fn refuses_to_overwrite_a_tracked_file() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["mv", "1.txt", "outer/2.txt"])
+                .unwrap_err(),
+            "fatal: destination exists, source=1.txt, destination=outer/2.txt\n",
+        );
+        assert!(cmd_helper.read_file(".git/index.lock").is_err());
+        assert_eq!("2", cmd_helper.read_file("outer/2.txt").unwrap());
+    }
+
+    #[test]
+    fn overwrites_a_tracked_file_when_forced() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper
+            .jit_cmd(&["mv", "-f", "1.txt", "outer/2.txt"])
+            .unwrap();
+
+        assert_eq!("1", cmd_helper.read_file("outer/2.txt").unwrap());
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, "outer/2.txt".to_string()),
+                (0o100644, "outer/inner/3.txt".to_string()),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    fn refuses_untracked_sources() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.write_file("untracked.txt", b"new").unwrap();
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["mv", "untracked.txt", "tracked.txt"])
+                .unwrap_err(),
+            "fatal: not under version control, source=untracked.txt, destination=tracked.txt\n",
+        );
+    }
+
+    #[test]
+    fn refuses_to_move_a_directory_into_itself() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert_output(
+            &cmd_helper.jit_cmd(&["mv", "outer", "outer/inner"]).unwrap_err(),
+            "fatal: can not move directory into itself, source=outer, destination=outer/inner/outer\n",
+        );
+    }
+
+    #[test]
+    fn refuses_to_move_a_file_onto_itself() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert_output(
+            &cmd_helper.jit_cmd(&["mv", "1.txt", "./1.txt"]).unwrap_err(),
+            "fatal: source and destination are the same, source=1.txt, destination=1.txt\n",
+        );
+    }
+
+    #[test]
+    fn undoes_earlier_renames_when_one_fails() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        // Through the link, `outer` would be renamed into itself,
+        // which only fails once `1.txt` has already moved
+        let root_path = cmd_helper.repo().root_path;
+        symlink(root_path.join("outer"), root_path.join("link")).unwrap();
+
+        assert!(cmd_helper
+            .jit_cmd(&["mv", "1.txt", "outer", "link"])
+            .unwrap_err()
+            .starts_with("fatal: renaming 'outer' failed"));
+        assert_eq!("1", cmd_helper.read_file("1.txt").unwrap());
+        assert!(cmd_helper.read_file("outer/1.txt").is_err());
+        assert_eq!("2", cmd_helper.read_file("outer/2.txt").unwrap());
+        assert!(cmd_helper.read_file(".git/index.lock").is_err());
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, "1.txt".to_string()),
+                (0o100644, "outer/2.txt".to_string()),
+                (0o100644, "outer/inner/3.txt".to_string()),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    fn checks_every_source_before_moving_anything() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper
+            .write_file("outer/inner/1.txt", b"untracked")
+            .unwrap();
+
+        assert!(cmd_helper
+            .jit_cmd(&["mv", "outer/2.txt", "1.txt", "outer/inner"])
+            .is_err());
+        assert_eq!("2", cmd_helper.read_file("outer/2.txt").unwrap());
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, "1.txt".to_string()),
+                (0o100644, "outer/2.txt".to_string()),
+                (0o100644, "outer/inner/3.txt".to_string()),
+            ])
+            .unwrap();
+    }
+
+    #[test]
+    fn dry_run_reports_without_renaming() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["mv", "-n", "1.txt", "outer/2.txt", "outer/inner"])
+            .unwrap();
+
+        assert_output(
+            &stdout,
+            "Renaming 1.txt to outer/inner/1.txt\nRenaming outer/2.txt to outer/inner/2.txt\n",
+        );
+        assert!(cmd_helper.read_file("outer/inner/1.txt").is_err());
+        assert!(cmd_helper.read_file(".git/index.lock").is_err());
+        cmd_helper
+            .assert_index(vec![
+                (0o100644, "1.txt".to_string()),
+                (0o100644, "outer/2.txt".to_string()),
+                (0o100644, "outer/inner/3.txt".to_string()),
+            ])
+            .unwrap();
+    }
+}
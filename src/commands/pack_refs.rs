@@ -0,0 +1,276 @@
+use crate::commands::CommandContext;
+use crate::repository::Repository;
+use std::io::{Read, Write};
+
+pub struct PackRefs<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> PackRefs<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> PackRefs<'a, I, O, E> {
+        let repo = ctx.repo();
+
+        PackRefs { repo, ctx }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let all = self
+            .ctx
+            .options
+            .as_ref()
+            .map(|o| o.is_present("all"))
+            .unwrap_or(false);
+
+        self.repo
+            .refs
+            .pack_refs(&mut self.repo.database, all)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::commit::Author;
+    use crate::database::object::Object;
+    use crate::database::tag::Tag;
+    use crate::refs::Ref;
+
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"one").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+    }
+
+    #[test]
+    fn packs_all_loose_refs() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.jit_cmd(&["branch", "topic"]).unwrap();
+        let oid = cmd_helper.read_file(".git/refs/heads/master").unwrap();
+
+        cmd_helper.jit_cmd(&["pack-refs", "--all"]).unwrap();
+
+        assert!(cmd_helper.read_file(".git/refs/heads/master").is_err());
+        assert!(cmd_helper.read_file(".git/refs/heads/topic").is_err());
+        assert_eq!(
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {0} refs/heads/master\n\
+                 {0} refs/heads/topic\n",
+                oid.trim()
+            ),
+            cmd_helper.read_file(".git/packed-refs").unwrap()
+        );
+    }
+
+    #[test]
+    fn leaves_branches_loose_without_all() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper.jit_cmd(&["pack-refs"]).unwrap();
+
+        assert!(cmd_helper.read_file(".git/refs/heads/master").is_ok());
+    }
+
+    #[test]
+    fn resolves_refs_from_packed_refs() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let oid = cmd_helper.read_file(".git/refs/heads/master").unwrap();
+        let oid = oid.trim();
+
+        cmd_helper
+            .write_file(
+                ".git/packed-refs",
+                format!(
+                    "# pack-refs with: peeled fully-peeled sorted \n\
+                     {0} refs/heads/master\n\
+                     {0} refs/tags/v1.0\n\
+                     {1} refs/tags/v2.0\n\
+                     ^{0}\n",
+                    oid, "1111111111111111111111111111111111111111"
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        cmd_helper.delete(".git/refs/heads/master").unwrap();
+
+        cmd_helper.jit_cmd(&["branch", "topic", "v1.0"]).unwrap();
+        assert_eq!(
+            format!("{}\n", oid),
+            cmd_helper.read_file(".git/refs/heads/topic").unwrap()
+        );
+
+        cmd_helper.write_file("file.txt", b"two").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("second");
+        assert_ne!(
+            format!("{}\n", oid),
+            cmd_helper.read_file(".git/refs/heads/master").unwrap()
+        );
+    }
+
+    #[test]
+    fn deletes_packed_branches() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.jit_cmd(&["branch", "topic"]).unwrap();
+        cmd_helper.jit_cmd(&["pack-refs", "--all"]).unwrap();
+
+        cmd_helper.jit_cmd(&["branch", "-D", "topic"]).unwrap();
+
+        assert!(!cmd_helper
+            .read_file(".git/packed-refs")
+            .unwrap()
+            .contains("refs/heads/topic"));
+        assert!(cmd_helper.jit_cmd(&["branch", "topic"]).is_ok());
+    }
+
+    #[test]
+    fn deletes_nested_branches_that_are_only_packed() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let oid = cmd_helper.read_file(".git/refs/heads/master").unwrap();
+        cmd_helper
+            .write_file(
+                ".git/packed-refs",
+                format!("{} refs/heads/feature/x\n", oid.trim()).as_bytes(),
+            )
+            .unwrap();
+
+        cmd_helper.jit_cmd(&["branch", "-D", "feature/x"]).unwrap();
+
+        assert!(!cmd_helper
+            .read_file(".git/packed-refs")
+            .unwrap()
+            .contains("refs/heads/feature/x"));
+        let root_path = cmd_helper.repo().root_path;
+        assert!(!root_path.join(".git/refs/heads/feature").exists());
+    }
+
+    #[test]
+    fn records_what_annotated_tags_peel_to() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let oid = cmd_helper.read_file(".git/refs/heads/master").unwrap();
+        let oid = oid.trim();
+
+        let tag = Tag::new(
+            oid,
+            "commit",
+            "v1.0",
+            Some(Author::parse(
+                "A. U. Thor <author@example.com> 1500000000 +0000",
+            )),
+            "Version 1.0\n",
+        );
+        cmd_helper.repo().database.store(&tag).unwrap();
+        cmd_helper
+            .write_file(
+                ".git/refs/tags/v1.0",
+                format!("{}\n", tag.get_oid()).as_bytes(),
+            )
+            .unwrap();
+        cmd_helper
+            .write_file(".git/refs/tags/v1.1", format!("{}\n", oid).as_bytes())
+            .unwrap();
+
+        cmd_helper.jit_cmd(&["pack-refs"]).unwrap();
+
+        assert!(cmd_helper.read_file(".git/refs/tags/v1.0").is_err());
+        assert!(cmd_helper.read_file(".git/refs/tags/v1.1").is_err());
+        assert_eq!(
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {0} refs/tags/v1.0\n\
+                 ^{1}\n\
+                 {1} refs/tags/v1.1\n",
+                tag.get_oid(),
+                oid
+            ),
+            cmd_helper.read_file(".git/packed-refs").unwrap()
+        );
+    }
+
+    #[test]
+    fn leaves_locked_refs_loose() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.jit_cmd(&["branch", "topic"]).unwrap();
+        cmd_helper
+            .write_file(".git/refs/heads/topic.lock", b"")
+            .unwrap();
+
+        cmd_helper.jit_cmd(&["pack-refs", "--all"]).unwrap();
+
+        assert!(cmd_helper.read_file(".git/refs/heads/master").is_err());
+        assert!(cmd_helper.read_file(".git/refs/heads/topic").is_ok());
+        assert!(cmd_helper.read_file(".git/refs/heads/topic.lock").is_ok());
+        assert!(!cmd_helper
+            .read_file(".git/packed-refs")
+            .unwrap()
+            .contains("refs/heads/topic"));
+    }
+
+    #[test]
+    fn lists_packed_and_loose_branches_by_name() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper.jit_cmd(&["branch", "topic"]).unwrap();
+        cmd_helper.jit_cmd(&["pack-refs", "--all"]).unwrap();
+        cmd_helper.jit_cmd(&["branch", "alpha"]).unwrap();
+
+        let names: Vec<_> = cmd_helper
+            .repo()
+            .refs
+            .list_branches()
+            .unwrap()
+            .into_iter()
+            .map(|r#ref| match r#ref {
+                Ref::SymRef { path } => path,
+                Ref::Ref { oid } => oid,
+            })
+            .collect();
+        assert_eq!(
+            vec!["refs/heads/alpha", "refs/heads/master", "refs/heads/topic"],
+            names
+        );
+    }
+
+    #[test]
+    fn fails_for_packed_refs_that_are_not_utf8() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper
+            .write_file(".git/packed-refs", b"\xff refs/heads/topic\n")
+            .unwrap();
+
+        assert_output(
+            &cmd_helper.jit_cmd(&["pack-refs", "--all"]).unwrap_err(),
+            "packed-refs is not valid UTF-8",
+        );
+        assert_output(
+            &cmd_helper.jit_cmd(&["branch"]).unwrap_err(),
+            "fatal: packed-refs is not valid UTF-8\n",
+        );
+        assert!(cmd_helper.read_file(".git/refs/heads/master").is_ok());
+        assert_eq!(
+            b"\xff refs/heads/topic\n".to_vec(),
+            cmd_helper.read_bytes(".git/packed-refs").unwrap()
+        );
+    }
+}
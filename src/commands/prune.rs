@@ -0,0 +1,300 @@
+use crate::commands::CommandContext;
+use crate::database::ParsedObject;
+use crate::reflog::{Reflog, NULL_OID};
+use crate::repository::Repository;
+use crate::util::parse_expiry;
+use chrono::Utc;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::time::UNIX_EPOCH;
+
+const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+pub struct Prune<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> Prune<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Prune<'a, I, O, E> {
+        let repo = ctx.repo();
+
+        Prune { repo, ctx }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        let dry_run = options.is_present("dry_run");
+        let verbose = options.is_present("verbose");
+
+        self.repo
+            .config
+            .load()
+            .map_err(|e| format!("fatal: {}\n", e))?;
+        let expire = options
+            .value_of("expire")
+            .map(|expire| expire.to_string())
+            .or_else(|| self.repo.config.get(&["gc", "pruneExpire"]))
+            .unwrap_or_else(|| DEFAULT_PRUNE_EXPIRE.to_string());
+        let cutoff =
+            parse_expiry(&expire, Utc::now().timestamp()).map_err(|e| format!("fatal: {}\n", e))?;
+
+        let roots = self.roots()?;
+        let reachable = self.mark_reachable(roots)?;
+
+        for oid in self.repo.database.loose_objects() {
+            if reachable.contains(&oid) || !self.is_expired(&oid, cutoff) {
+                continue;
+            }
+
+            if dry_run || verbose {
+                writeln!(
+                    self.ctx.stdout,
+                    "{} {}",
+                    oid,
+                    self.repo.database.load(&oid).obj_type()
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            if !dry_run {
+                self.repo
+                    .database
+                    .remove_loose(&oid)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Objects that are kept along with everything they reference:
+    /// HEAD, every ref, every reflog entry and the index
+    fn roots(&mut self) -> Result<Vec<String>, String> {
+        let refs = &self.repo.refs;
+        let mut roots: Vec<String> = refs.read_head().into_iter().collect();
+        roots.extend(
+            refs.list_all_refs()
+                .map_err(|e| format!("fatal: {}\n", e))?
+                .iter()
+                .filter_map(|r#ref| refs.read_oid(r#ref)),
+        );
+
+        for name in refs.list_reflogs() {
+            let reflog = Reflog::load(&refs.reflog_path(&name)).map_err(|e| e.to_string())?;
+            for entry in reflog.entries() {
+                roots.push(entry.old_oid.clone());
+                roots.push(entry.new_oid.clone());
+            }
+        }
+
+        self.repo
+            .index
+            .load()
+            .map_err(|e| format!("fatal: {}\n", e))?;
+        roots.extend(
+            self.repo
+                .index
+                .entries
+                .values()
+                .map(|entry| entry.oid.clone()),
+        );
+
+        roots.retain(|oid| oid != NULL_OID);
+        Ok(roots)
+    }
+
+    fn mark_reachable(&mut self, roots: Vec<String>) -> Result<HashSet<String>, String> {
+        let pack_indexes = self.repo.database.pack_indexes();
+        let mut reachable = HashSet::new();
+        let mut pending = roots;
+
+        while let Some(oid) = pending.pop() {
+            if !reachable.insert(oid.clone()) {
+                continue;
+            }
+
+            if self.repo.database.loose_mtime(&oid).is_none() {
+                // Whatever a packed object refers to can't be found
+                // without reading the pack, and would look unreachable
+                if pack_indexes.iter().any(|index| index.contains(&oid)) {
+                    return Err(format!(
+                        "fatal: object {} is packed; pruning packed repositories is not supported\n",
+                        oid
+                    ));
+                }
+                // Missing objects, eg. from old reflog entries, have
+                // nothing to protect
+                continue;
+            }
+
+            match self.repo.database.load(&oid) {
+                ParsedObject::Commit(commit) => {
+                    pending.push(commit.tree_oid.clone());
+                    pending.extend(commit.parents.iter().cloned());
+                }
+                ParsedObject::Tree(tree) => {
+                    pending.extend(tree.entries.values().map(|entry| entry.get_oid()));
+                }
+                ParsedObject::Tag(tag) => pending.push(tag.object.clone()),
+                ParsedObject::Blob(_) => (),
+            }
+        }
+
+        Ok(reachable)
+    }
+
+    /// Objects written after the cutoff are left alone, since another
+    /// process may be about to reference them
+    fn is_expired(&self, oid: &str, cutoff: i64) -> bool {
+        let mtime = self
+            .repo
+            .database
+            .loose_mtime(oid)
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64);
+
+        match mtime {
+            Some(mtime) => mtime <= cutoff,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::blob::Blob;
+    use crate::database::object::Object;
+
+    fn blob_oid(contents: &str) -> String {
+        Blob::new(contents.as_bytes()).get_oid()
+    }
+
+    fn object_path(oid: &str) -> String {
+        format!(".git/objects/{}/{}", &oid[0..2], &oid[2..])
+    }
+
+    fn head_oid(cmd_helper: &CommandHelper) -> String {
+        cmd_helper
+            .read_file(".git/refs/heads/master")
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("file.txt", b"committed").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+
+        // Staged and then replaced, so nothing refers to it
+        cmd_helper.write_file("file.txt", b"dropped").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.write_file("file.txt", b"staged").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+    }
+
+    #[test]
+    fn keeps_recent_unreachable_objects() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper.jit_cmd(&["prune"]).unwrap();
+
+        assert!(cmd_helper
+            .read_bytes(&object_path(&blob_oid("dropped")))
+            .is_ok());
+    }
+
+    #[test]
+    fn deletes_unreachable_objects_past_the_grace_period() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper.jit_cmd(&["prune", "--expire=now"]).unwrap();
+
+        assert!(cmd_helper
+            .read_bytes(&object_path(&blob_oid("dropped")))
+            .is_err());
+        assert!(cmd_helper
+            .read_bytes(&object_path(&blob_oid("committed")))
+            .is_ok());
+        assert!(cmd_helper
+            .read_bytes(&object_path(&blob_oid("staged")))
+            .is_ok());
+        assert!(cmd_helper
+            .read_bytes(&object_path(&head_oid(&cmd_helper)))
+            .is_ok());
+    }
+
+    #[test]
+    fn reads_the_grace_period_from_config() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper
+            .write_file(".git/config", b"[gc]\n\tpruneExpire = now\n")
+            .unwrap();
+
+        cmd_helper.jit_cmd(&["prune"]).unwrap();
+
+        assert!(cmd_helper
+            .read_bytes(&object_path(&blob_oid("dropped")))
+            .is_err());
+    }
+
+    #[test]
+    fn keeps_objects_referenced_from_reflogs() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let first = head_oid(&cmd_helper);
+        cmd_helper.commit("second");
+        let second = head_oid(&cmd_helper);
+
+        // Rewind master, leaving the second commit only in the reflog
+        cmd_helper
+            .write_file(".git/refs/heads/master", first.as_bytes())
+            .unwrap();
+        cmd_helper
+            .write_file(
+                ".git/logs/refs/heads/master",
+                format!(
+                    "{} {} A. U. Thor <author@example.com> 1500000000 +0000\tcommit: second\n",
+                    first, second
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+
+        cmd_helper.jit_cmd(&["prune", "--expire=now"]).unwrap();
+
+        assert!(cmd_helper.read_bytes(&object_path(&second)).is_ok());
+        assert!(cmd_helper
+            .read_bytes(&object_path(&blob_oid("staged")))
+            .is_ok());
+    }
+
+    #[test]
+    fn dry_run_lists_objects_without_deleting_them() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["prune", "-n", "--expire", "now"])
+            .unwrap();
+
+        let dropped = blob_oid("dropped");
+        assert_output(&stdout, &format!("{} blob\n", dropped));
+        assert!(cmd_helper.read_bytes(&object_path(&dropped)).is_ok());
+    }
+}
@@ -0,0 +1,362 @@
+use crate::commands::CommandContext;
+use crate::database::object::Object;
+use crate::reflog;
+use crate::repository::Repository;
+use crate::rev_list::RevList;
+use crate::util::parse_expiry;
+use chrono::Utc;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+const DEFAULT_REFLOG_EXPIRE: &str = "90.days.ago";
+const DEFAULT_REFLOG_EXPIRE_UNREACHABLE: &str = "30.days.ago";
+const USAGE: &str = "usage: rug reflog expire [--expire=<time>] \
+                     [--expire-unreachable=<time>] [--all | <refs>...]\n";
+
+pub struct Reflog<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> Reflog<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Reflog<'a, I, O, E> {
+        let repo = ctx.repo();
+
+        Reflog { repo, ctx }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        match options.subcommand() {
+            ("expire", Some(expire_options)) => self.expire(expire_options),
+            _ => Err(USAGE.to_string()),
+        }
+    }
+
+    /// Drop entries older than `gc.reflogExpire`, and entries that are
+    /// no longer reachable from the ref's tip once older than
+    /// `gc.reflogExpireUnreachable`
+    fn expire(&mut self, options: &clap::ArgMatches) -> Result<(), String> {
+        let dry_run = options.is_present("dry_run");
+
+        self.repo
+            .config
+            .load()
+            .map_err(|e| format!("fatal: {}\n", e))?;
+        let expire = self.cutoff(options, "expire", "reflogExpire", DEFAULT_REFLOG_EXPIRE)?;
+        let expire_unreachable = self.cutoff(
+            options,
+            "expire_unreachable",
+            "reflogExpireUnreachable",
+            DEFAULT_REFLOG_EXPIRE_UNREACHABLE,
+        )?;
+
+        let names = if options.is_present("all") {
+            self.repo.refs.list_reflogs()
+        } else {
+            let mut names = vec![];
+            for arg in options.values_of("args").into_iter().flatten() {
+                match self.repo.refs.reflog_name(arg) {
+                    Some(name) => names.push(name),
+                    None => return Err(format!("error: {} points nowhere!\n", arg)),
+                }
+            }
+            names
+        };
+        if names.is_empty() && !options.is_present("all") {
+            return Err("error: no reflog specified to expire\n".to_string());
+        }
+
+        for name in names {
+            let mut reflog = reflog::Reflog::load(&self.repo.refs.reflog_path(&name))
+                .map_err(|e| e.to_string())?;
+            let reachable = self.reachable_from(&name)?;
+
+            let pruned = reflog.retain(|entry| {
+                entry.time > expire
+                    && (entry.time > expire_unreachable || reachable.contains(&entry.new_oid))
+            });
+
+            if dry_run {
+                for entry in pruned {
+                    writeln!(self.ctx.stdout, "would prune {}", entry.message)
+                        .map_err(|e| e.to_string())?;
+                }
+                continue;
+            }
+
+            if !pruned.is_empty() {
+                reflog.write().map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cutoff from the command-line option, falling back to `gc.<key>`
+    /// in the config and then git's default
+    fn cutoff(
+        &self,
+        options: &clap::ArgMatches,
+        option: &str,
+        key: &str,
+        default: &str,
+    ) -> Result<i64, String> {
+        let value = options
+            .value_of(option)
+            .map(|value| value.to_string())
+            .or_else(|| self.repo.config.get(&["gc", key]))
+            .unwrap_or_else(|| default.to_string());
+
+        parse_expiry(&value, Utc::now().timestamp()).map_err(|e| format!("fatal: {}\n", e))
+    }
+
+    /// Commits in the history of the ref's current value
+    fn reachable_from(&mut self, name: &str) -> Result<HashSet<String>, String> {
+        let tip = if name == "HEAD" {
+            self.repo.refs.read_head()
+        } else {
+            self.repo.refs.read_ref(name)
+        };
+
+        let tips: Vec<String> = tip.into_iter().collect();
+        RevList::from_oids(&mut self.repo, &tips)?
+            .map(|commit| commit.map(|commit| commit.get_oid()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+    use crate::database::pack_index::tests::v2_index;
+    use chrono::Utc;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn head_oid(cmd_helper: &CommandHelper) -> String {
+        cmd_helper
+            .read_file(".git/refs/heads/master")
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    fn reflog_line(old: &str, new: &str, age_in_days: i64, message: &str) -> String {
+        format!(
+            "{} {} A. U. Thor <author@example.com> {} +0000\t{}\n",
+            old,
+            new,
+            Utc::now().timestamp() - age_in_days * DAY,
+            message
+        )
+    }
+
+    /// Builds master with two commits, plus a commit that only a
+    /// reflog entry refers to, and writes a reflog for master with
+    /// entries of various ages
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        let mut graph = GraphBuilder::new(cmd_helper);
+        graph
+            .commit("first", &[])
+            .commit("abandoned", &["first"])
+            .commit("second", &["first"])
+            .branch("master", "second");
+        let (first, abandoned, second) = (
+            graph.oid("first"),
+            graph.oid("abandoned"),
+            graph.oid("second"),
+        );
+
+        let reflog = [
+            reflog_line(&"0".repeat(40), &first, 100, "commit (initial): first"),
+            reflog_line(&first, &abandoned, 40, "commit: abandoned"),
+            reflog_line(&abandoned, &first, 40, "reset: moving to first"),
+            reflog_line(&first, &second, 10, "commit: second"),
+        ]
+        .concat();
+        cmd_helper
+            .write_file(".git/logs/refs/heads/master", reflog.as_bytes())
+            .unwrap();
+    }
+
+    /// The new oid of the reflog entry at `index`
+    fn reflog_line_oid(cmd_helper: &CommandHelper, index: usize) -> String {
+        let reflog = cmd_helper.read_file(".git/logs/refs/heads/master").unwrap();
+        reflog.lines().nth(index).unwrap()[41..81].to_string()
+    }
+
+    fn reflog_messages(cmd_helper: &CommandHelper) -> Vec<String> {
+        cmd_helper
+            .read_file(".git/logs/refs/heads/master")
+            .unwrap()
+            .lines()
+            .map(|line| line.split_once('\t').unwrap().1.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn expires_old_and_unreachable_entries() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper.jit_cmd(&["reflog", "expire", "master"]).unwrap();
+
+        assert_eq!(
+            vec!["reset: moving to first", "commit: second"],
+            reflog_messages(&cmd_helper)
+        );
+    }
+
+    #[test]
+    fn honours_reflog_expiry_config() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper
+            .write_file(
+                ".git/config",
+                b"[gc]\n\treflogExpire = never\n\treflogExpireUnreachable = 5.days.ago\n",
+            )
+            .unwrap();
+
+        cmd_helper.jit_cmd(&["reflog", "expire", "--all"]).unwrap();
+
+        assert_eq!(
+            vec![
+                "commit (initial): first",
+                "reset: moving to first",
+                "commit: second"
+            ],
+            reflog_messages(&cmd_helper)
+        );
+    }
+
+    #[test]
+    fn fails_for_expiry_dates_out_of_range() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper
+            .write_file(
+                ".git/config",
+                b"[gc]\n\treflogExpire = 99999999999999 weeks ago\n",
+            )
+            .unwrap();
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["reflog", "expire", "--all"])
+                .unwrap_err(),
+            "fatal: invalid expiry date '99999999999999 weeks ago'\n",
+        );
+        assert_eq!(4, reflog_messages(&cmd_helper).len());
+    }
+
+    #[test]
+    fn options_override_config() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper
+            .write_file(".git/config", b"[gc]\n\treflogExpire = never\n")
+            .unwrap();
+
+        cmd_helper
+            .jit_cmd(&["reflog", "expire", "--expire=20.days.ago", "--all"])
+            .unwrap();
+
+        assert_eq!(vec!["commit: second"], reflog_messages(&cmd_helper));
+    }
+
+    #[test]
+    fn dry_run_reports_entries_without_expiring_them() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper
+            .jit_cmd(&["reflog", "expire", "-n", "--all"])
+            .unwrap();
+
+        assert_output(
+            &stdout,
+            "would prune commit (initial): first\nwould prune commit: abandoned\n",
+        );
+        assert_eq!(4, reflog_messages(&cmd_helper).len());
+    }
+
+    #[test]
+    fn keeps_the_bytes_of_remaining_lines() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let mut reflog = cmd_helper
+            .read_bytes(".git/logs/refs/heads/master")
+            .unwrap();
+        let head = head_oid(&cmd_helper);
+        reflog.extend_from_slice(b"not a reflog line\n");
+        reflog.extend_from_slice(reflog_line(&head, &head, 1, "commit: caf").as_bytes());
+        reflog.insert(reflog.len() - 1, 0xe9);
+        cmd_helper
+            .write_file(".git/logs/refs/heads/master", &reflog)
+            .unwrap();
+
+        cmd_helper.jit_cmd(&["reflog", "expire", "master"]).unwrap();
+
+        let lines: Vec<_> = reflog.split_inclusive(|&byte| byte == b'\n').collect();
+        assert_eq!(
+            lines[2..].concat(),
+            cmd_helper
+                .read_bytes(".git/logs/refs/heads/master")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn fails_for_history_stored_in_packs() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        let first = reflog_line_oid(&cmd_helper, 0);
+        let object_path = format!(".git/objects/{}/{}", &first[0..2], &first[2..]);
+
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.pack", b"PACK")
+            .unwrap();
+        cmd_helper
+            .write_file(".git/objects/pack/pack-1.idx", &v2_index(&[&first]))
+            .unwrap();
+        cmd_helper.delete(&object_path).unwrap();
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["reflog", "expire", "master"])
+                .unwrap_err(),
+            &format!(
+                "fatal: object {} is packed; reading packs is not supported\n",
+                first
+            ),
+        );
+        assert_eq!(4, reflog_messages(&cmd_helper).len());
+    }
+
+    #[test]
+    fn fails_for_refs_without_a_reflog() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["reflog", "expire", "topic"])
+                .unwrap_err(),
+            "error: topic points nowhere!\n",
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use crate::commands::CommandContext;
+use crate::mailmap::Mailmap;
+use crate::pager::Pager;
+use crate::repository::Repository;
+use crate::rev_list::RevList;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+pub struct Shortlog<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> Shortlog<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> Shortlog<'a, I, O, E> {
+        let repo = ctx.repo();
+
+        Shortlog { repo, ctx }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        let args: Vec<_> = if let Some(args) = options.values_of("args") {
+            args.collect()
+        } else {
+            vec![]
+        };
+        let summary = options.is_present("summary");
+        let numbered = options.is_present("numbered");
+        let email = options.is_present("email");
+
+        let mailmap =
+            Mailmap::load(&self.repo.workspace.abs_path(".mailmap")).map_err(|e| e.to_string())?;
+
+        // Subjects for each author, newest first as walked
+        let mut authors: HashMap<String, Vec<String>> = HashMap::new();
+        for commit in RevList::new(&mut self.repo, &args)? {
+            let commit = commit?;
+            let (name, mail) = mailmap.lookup(&commit.author.name, &commit.author.email);
+            let author = if email {
+                format!("{} <{}>", name, mail)
+            } else {
+                name
+            };
+            authors.entry(author).or_default().push(commit.title_line());
+        }
+
+        let mut authors: Vec<_> = authors.into_iter().collect();
+        if numbered {
+            authors.sort_by(|(a, a_subjects), (b, b_subjects)| {
+                b_subjects.len().cmp(&a_subjects.len()).then(a.cmp(b))
+            });
+        } else {
+            authors.sort();
+        }
+
+        if self.ctx.use_pager {
+            Pager::setup_pager(self.ctx.env);
+        }
+
+        for (author, subjects) in authors {
+            if summary {
+                writeln!(self.ctx.stdout, "{:>6}\t{}", subjects.len(), author)
+                    .map_err(|e| e.to_string())?;
+                continue;
+            }
+
+            writeln!(self.ctx.stdout, "{} ({}):", author, subjects.len())
+                .map_err(|e| e.to_string())?;
+            for subject in subjects.iter().rev() {
+                writeln!(self.ctx.stdout, "      {}", subject).map_err(|e| e.to_string())?;
+            }
+            writeln!(self.ctx.stdout).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn commit_as(cmd_helper: &mut CommandHelper, name: &str, email: &str, message: &str) {
+        cmd_helper
+            .write_file("file.txt", message.as_bytes())
+            .unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit_as(name, email, message);
+    }
+
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.set_env("GIT_PAGER", "cat");
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+
+        commit_as(cmd_helper, "Bob", "bob@example.com", "first");
+        commit_as(cmd_helper, "Alice", "alice@example.com", "second");
+        commit_as(cmd_helper, "Bob", "bob@example.com", "third");
+        commit_as(cmd_helper, "bobby", "BOB@work.example.com", "fourth");
+    }
+
+    #[test]
+    fn groups_subjects_by_author() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["shortlog"]).unwrap();
+        assert_output(
+            &stdout,
+            "Alice (1):
+      second
+
+Bob (2):
+      first
+      third
+
+bobby (1):
+      fourth
+
+",
+        );
+    }
+
+    #[test]
+    fn prints_counts_sorted_by_number_of_commits() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["shortlog", "-sn"]).unwrap();
+        assert_output(&stdout, "     2\tBob\n     1\tAlice\n     1\tbobby\n");
+    }
+
+    #[test]
+    fn canonicalizes_authors_with_mailmap() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+        cmd_helper
+            .write_file(
+                ".mailmap",
+                b"Bob Builder <bob@example.com>\nBob Builder <bob@example.com> <bob@work.example.com>\n",
+            )
+            .unwrap();
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["shortlog", "-sne"]).unwrap();
+        assert_output(
+            &stdout,
+            "     3\tBob Builder <bob@example.com>\n     1\tAlice <alice@example.com>\n",
+        );
+    }
+
+    #[test]
+    fn limits_history_to_the_given_revisions() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        let (stdout, _) = cmd_helper.jit_cmd(&["shortlog", "-s", "@~2"]).unwrap();
+        assert_output(&stdout, "     1\tAlice\n     1\tBob\n");
+    }
+}
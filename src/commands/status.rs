@@ -1,4 +1,4 @@
-use crate::commands::CommandContext;
+use crate::commands::{paint, CommandContext};
 use crate::repository::{ChangeType, Repository};
 use colored::*;
 use std::collections::HashMap;
@@ -45,9 +45,7 @@ where
         O: Write,
         E: Write,
     {
-        let working_dir = &ctx.dir;
-        let root_path = working_dir.as_path();
-        let repo = Repository::new(&root_path);
+        let repo = ctx.repo();
 
         Status { repo, ctx }
     }
@@ -68,11 +66,12 @@ where
 
     fn print_porcelain_format(&mut self) -> Result<(), String> {
         for file in &self.repo.changed {
-            println!("{} {}", self.status_for(file), file);
+            writeln!(self.ctx.stdout, "{} {}", self.status_for(file), file)
+                .map_err(|e| e.to_string())?;
         }
 
         for file in &self.repo.untracked {
-            println!("?? {}", file);
+            writeln!(self.ctx.stdout, "?? {}", file).map_err(|e| e.to_string())?;
         }
 
         Ok(())
@@ -89,44 +88,59 @@ where
     }
 
     fn print_index_changes(&mut self, message: &str, style: &str) -> Result<(), String> {
-        println!("{}", message);
+        writeln!(self.ctx.stdout, "{}", message).map_err(|e| e.to_string())?;
 
         for (path, change_type) in &self.repo.index_changes {
             if let Some(status) = LONG_STATUS.get(change_type) {
-                println!(
+                writeln!(
+                    self.ctx.stdout,
                     "{}",
-                    format!("\t{:width$}{}", status, path, width = LABEL_WIDTH).color(style)
-                );
+                    paint(
+                        self.ctx.use_color,
+                        format!("\t{:width$}{}", status, path, width = LABEL_WIDTH).color(style)
+                    )
+                )
+                .map_err(|e| e.to_string())?;
             }
         }
 
-        println!();
+        writeln!(self.ctx.stdout).map_err(|e| e.to_string())?;
         Ok(())
     }
 
     fn print_workspace_changes(&mut self, message: &str, style: &str) -> Result<(), String> {
-        println!("{}", message);
+        writeln!(self.ctx.stdout, "{}", message).map_err(|e| e.to_string())?;
 
         for (path, change_type) in &self.repo.workspace_changes {
             if let Some(status) = LONG_STATUS.get(change_type) {
-                println!(
+                writeln!(
+                    self.ctx.stdout,
                     "{}",
-                    format!("\t{:width$}{}", status, path, width = LABEL_WIDTH).color(style)
-                );
+                    paint(
+                        self.ctx.use_color,
+                        format!("\t{:width$}{}", status, path, width = LABEL_WIDTH).color(style)
+                    )
+                )
+                .map_err(|e| e.to_string())?;
             }
         }
 
-        println!();
+        writeln!(self.ctx.stdout).map_err(|e| e.to_string())?;
         Ok(())
     }
 
     fn print_untracked_files(&mut self, message: &str, style: &str) -> Result<(), String> {
-        println!("{}", message);
+        writeln!(self.ctx.stdout, "{}", message).map_err(|e| e.to_string())?;
 
         for path in &self.repo.untracked {
-            println!("{}", format!("\t{}", path).color(style));
+            writeln!(
+                self.ctx.stdout,
+                "{}",
+                paint(self.ctx.use_color, format!("\t{}", path).color(style))
+            )
+            .map_err(|e| e.to_string())?;
         }
-        println!();
+        writeln!(self.ctx.stdout).map_err(|e| e.to_string())?;
         Ok(())
     }
 
@@ -152,11 +166,16 @@ where
         }
 
         if !self.repo.workspace_changes.is_empty() {
-            println!("no changes added to commit");
+            writeln!(self.ctx.stdout, "no changes added to commit").map_err(|e| e.to_string())?;
         } else if !self.repo.untracked.is_empty() {
-            println!("nothing added to commit but untracked files present");
+            writeln!(
+                self.ctx.stdout,
+                "nothing added to commit but untracked files present"
+            )
+            .map_err(|e| e.to_string())?;
         } else {
-            println!("nothing to commit, working tree clean");
+            writeln!(self.ctx.stdout, "nothing to commit, working tree clean")
+                .map_err(|e| e.to_string())?;
         }
 
         Ok(())
@@ -0,0 +1,170 @@
+use crate::commands::CommandContext;
+use crate::repository::Repository;
+use crate::util::normalize_path;
+use std::io::{Read, Write};
+
+pub struct UpdateIndex<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    repo: Repository,
+    ctx: CommandContext<'a, I, O, E>,
+}
+
+impl<'a, I, O, E> UpdateIndex<'a, I, O, E>
+where
+    I: Read,
+    O: Write,
+    E: Write,
+{
+    pub fn new(ctx: CommandContext<'a, I, O, E>) -> UpdateIndex<'a, I, O, E> {
+        let repo = ctx.repo();
+
+        UpdateIndex { repo, ctx }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let options = self.ctx.options.as_ref().unwrap().clone();
+        let paths: Vec<_> = match options.values_of("args") {
+            Some(args) => args.map(normalize_path).collect(),
+            None => vec![],
+        };
+
+        // `--no-*` wins over its counterpart, as the later option
+        // would in git
+        let assume_unchanged = Self::flag_value(&options, "assume_unchanged");
+        let skip_worktree = Self::flag_value(&options, "skip_worktree");
+
+        self.repo
+            .index
+            .load_for_update()
+            .map_err(|e| e.to_string())?;
+
+        for path in &paths {
+            if !self.repo.index.is_tracked_file(path) {
+                self.repo.index.release_lock().map_err(|e| e.to_string())?;
+                return Err(format!("fatal: Unable to mark file {}\n", path));
+            }
+
+            if let Some(value) = assume_unchanged {
+                self.repo.index.set_assume_unchanged(path, value);
+            }
+            if let Some(value) = skip_worktree {
+                self.repo.index.set_skip_worktree(path, value);
+            }
+        }
+
+        self.repo.index.write_updates().map_err(|e| e.to_string())
+    }
+
+    /// `Some(true)` for `--<flag>`, `Some(false)` for `--no-<flag>`
+    /// and `None` when the flag is left alone
+    fn flag_value(options: &clap::ArgMatches, flag: &str) -> Option<bool> {
+        if options.is_present(format!("no_{}", flag)) {
+            Some(false)
+        } else if options.is_present(flag) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::tests::*;
+
+    fn before(cmd_helper: &mut CommandHelper) {
+        cmd_helper.jit_cmd(&["init"]).unwrap();
+        cmd_helper.write_file("1.txt", b"one").unwrap();
+        cmd_helper.write_file("a/2.txt", b"two").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+        cmd_helper.commit("first");
+    }
+
+    fn index_version(cmd_helper: &CommandHelper) -> u32 {
+        let index = cmd_helper.read_bytes(".git/index").unwrap();
+        u32::from_be_bytes([index[4], index[5], index[6], index[7]])
+    }
+
+    #[test]
+    fn status_ignores_assume_unchanged_files() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper
+            .jit_cmd(&["update-index", "--assume-unchanged", "1.txt"])
+            .unwrap();
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+
+        cmd_helper.assert_status("");
+        assert_eq!(2, index_version(&cmd_helper));
+    }
+
+    #[test]
+    fn status_ignores_skip_worktree_files() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper
+            .jit_cmd(&["update-index", "--skip-worktree", "1.txt", "./a/2.txt"])
+            .unwrap();
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+        cmd_helper.delete("a").unwrap();
+
+        cmd_helper.assert_status("");
+        assert_eq!(3, index_version(&cmd_helper));
+    }
+
+    #[test]
+    fn add_leaves_flagged_entries_alone() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper
+            .jit_cmd(&["update-index", "--skip-worktree", "1.txt"])
+            .unwrap();
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+        cmd_helper.write_file("a/2.txt", b"changed").unwrap();
+        cmd_helper.jit_cmd(&["add", "."]).unwrap();
+
+        cmd_helper
+            .jit_cmd(&["update-index", "--no-skip-worktree", "1.txt"])
+            .unwrap();
+        cmd_helper.assert_status(" M 1.txt\nM  a/2.txt\n");
+        assert_eq!(2, index_version(&cmd_helper));
+    }
+
+    #[test]
+    fn clearing_a_flag_restores_checks() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        cmd_helper
+            .jit_cmd(&["update-index", "--assume-unchanged", "1.txt"])
+            .unwrap();
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+        cmd_helper
+            .jit_cmd(&["update-index", "--no-assume-unchanged", "1.txt"])
+            .unwrap();
+
+        cmd_helper.assert_status(" M 1.txt\n");
+    }
+
+    #[test]
+    fn fails_for_untracked_paths() {
+        let mut cmd_helper = CommandHelper::new();
+        before(&mut cmd_helper);
+
+        assert_output(
+            &cmd_helper
+                .jit_cmd(&["update-index", "--skip-worktree", "1.txt", "a"])
+                .unwrap_err(),
+            "fatal: Unable to mark file a\n",
+        );
+        cmd_helper.write_file("1.txt", b"changed").unwrap();
+        cmd_helper.assert_status(" M 1.txt\n");
+    }
+}
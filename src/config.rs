@@ -0,0 +1,256 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    static ref SECTION_LINE: Regex =
+        Regex::new(r#"^\s*\[\s*([a-zA-Z0-9.-]+)(?:\s+"((?:\\.|[^"\\])*)")?\s*\]\s*$"#).unwrap();
+    static ref VARIABLE_LINE: Regex =
+        Regex::new(r"^\s*([a-zA-Z][a-zA-Z0-9-]*)\s*(?:=\s*(.*))?$").unwrap();
+}
+
+/// Reader for git's INI-style config files, eg. `.git/config`.
+/// Section and variable names are case-insensitive, subsection names
+/// are not.
+pub struct Config {
+    path: PathBuf,
+    // Keyed by `section`, `section.subsection`, each holding
+    // (variable, value) pairs in file order
+    sections: HashMap<String, Vec<(String, String)>>,
+}
+
+impl Config {
+    pub fn new(path: &Path) -> Config {
+        Config {
+            path: path.to_path_buf(),
+            sections: HashMap::new(),
+        }
+    }
+
+    /// Read the config file. A missing file is treated as empty.
+    pub fn load(&mut self) -> Result<(), io::Error> {
+        self.sections = HashMap::new();
+
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let mut section: Option<String> = None;
+        for (line_no, line) in contents.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+                continue;
+            }
+
+            if let Some(caps) = SECTION_LINE.captures(line) {
+                let name = caps[1].to_lowercase();
+                section = Some(match caps.get(2) {
+                    Some(subsection) => format!("{}.{}", name, unescape(subsection.as_str())),
+                    None => name,
+                });
+            } else if let (Some(caps), Some(section)) = (VARIABLE_LINE.captures(line), &section) {
+                let value = caps
+                    .get(2)
+                    .map(|value| parse_value(value.as_str()))
+                    .unwrap_or_else(|| "true".to_string());
+                self.sections
+                    .entry(section.clone())
+                    .or_default()
+                    .push((caps[1].to_lowercase(), value));
+            } else {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("bad config line {} in file {:?}", line_no + 1, self.path),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Look up a variable by `[section, name]` or
+    /// `[section, subsection, name]`. When a variable is set more than
+    /// once, the last value wins.
+    pub fn get(&self, key: &[&str]) -> Option<String> {
+        self.get_all(key).pop()
+    }
+
+    /// Look up a size such as `512`, `64k`, `32m` or `1g`, failing
+    /// for a value that isn't one
+    pub fn get_size(&self, key: &[&str]) -> Result<Option<usize>, String> {
+        let value = match self.get(key) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let lowercase = value.to_lowercase();
+        let (digits, factor) = match lowercase.chars().last() {
+            Some('k') => (&lowercase[..lowercase.len() - 1], 1 << 10),
+            Some('m') => (&lowercase[..lowercase.len() - 1], 1 << 20),
+            Some('g') => (&lowercase[..lowercase.len() - 1], 1 << 30),
+            _ => (&lowercase[..], 1),
+        };
+
+        let size = digits
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_mul(factor));
+        match size {
+            Some(size) => Ok(Some(size)),
+            None => Err(format!(
+                "bad numeric config value '{}' for '{}': invalid unit",
+                value,
+                key.join(".").to_lowercase()
+            )),
+        }
+    }
+
+    pub fn get_all(&self, key: &[&str]) -> Vec<String> {
+        let (section, name) = match key {
+            [section, name] => (section.to_lowercase(), name.to_lowercase()),
+            [section, subsection, name] => (
+                format!("{}.{}", section.to_lowercase(), subsection),
+                name.to_lowercase(),
+            ),
+            _ => return vec![],
+        };
+
+        self.sections
+            .get(&section)
+            .map(|variables| {
+                variables
+                    .iter()
+                    .filter(|(variable, _)| *variable == name)
+                    .map(|(_, value)| value.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Strip quotes, escapes and trailing comments from a raw value
+fn parse_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut chars = raw.trim().chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(escaped) => value.push(escaped),
+                None => (),
+            },
+            c => value.push(c),
+        }
+    }
+
+    value.trim_end().to_string()
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                unescaped.push(escaped);
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::generate_temp_name;
+
+    fn load_config(contents: &str) -> Result<Config, io::Error> {
+        let mut temp_name = generate_temp_name();
+        temp_name.push_str("_rug_test_config");
+        let path = std::env::temp_dir().join(temp_name);
+        fs::write(&path, contents)?;
+
+        let mut config = Config::new(&path);
+        let result = config.load();
+        fs::remove_file(&path)?;
+        result.map(|_| config)
+    }
+
+    #[test]
+    fn reads_sections_and_subsections() -> Result<(), io::Error> {
+        let config = load_config(
+            "[core]
+\tbare = false
+\tfilemode
+# a comment
+[Branch \"Topic\"]
+\tremote = origin ; trailing comment
+\tmerge = refs/heads/topic
+[gc]
+\treflogExpire = \"30 days\"
+\treflogexpire = 60.days
+",
+        )?;
+
+        assert_eq!(Some("false".to_string()), config.get(&["core", "bare"]));
+        assert_eq!(Some("true".to_string()), config.get(&["core", "fileMode"]));
+        assert_eq!(
+            Some("origin".to_string()),
+            config.get(&["branch", "Topic", "remote"])
+        );
+        assert_eq!(None, config.get(&["branch", "topic", "remote"]));
+        assert_eq!(Some("60.days".to_string()), config.get(&["gc", "reflogExpire"]));
+        assert_eq!(
+            vec!["30 days".to_string(), "60.days".to_string()],
+            config.get_all(&["gc", "reflogexpire"])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reads_sizes() -> Result<(), io::Error> {
+        let config = load_config(
+            "[core]
+\tplain = 512
+\tkilo = 64k
+\tmega = 32M
+\tgiga = 1g
+\tbad = lots
+",
+        )?;
+
+        assert_eq!(Ok(Some(512)), config.get_size(&["core", "plain"]));
+        assert_eq!(Ok(Some(64 * 1024)), config.get_size(&["core", "kilo"]));
+        assert_eq!(
+            Ok(Some(32 * 1024 * 1024)),
+            config.get_size(&["core", "mega"])
+        );
+        assert_eq!(
+            Ok(Some(1024 * 1024 * 1024)),
+            config.get_size(&["core", "giga"])
+        );
+        assert_eq!(
+            Err("bad numeric config value 'lots' for 'core.bad': invalid unit".to_string()),
+            config.get_size(&["core", "bad"])
+        );
+        assert_eq!(Ok(None), config.get_size(&["core", "missing"]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(load_config("[core]\n= oops\n").is_err());
+    }
+}
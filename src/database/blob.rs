@@ -23,7 +23,7 @@ impl Object for Blob {
         self.data.clone()
     }
 
-    fn parse(s: &[u8]) -> ParsedObject {
-        ParsedObject::Blob(Blob::new(s))
+    fn parse(s: &[u8]) -> Result<ParsedObject, String> {
+        Ok(ParsedObject::Blob(Blob::new(s)))
     }
 }
@@ -0,0 +1,132 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::database::ParsedObject;
+
+/// Default for `core.objectCacheLimit`
+pub const DEFAULT_CACHE_LIMIT: usize = 64 * 1024 * 1024;
+
+struct CacheEntry {
+    object: ParsedObject,
+    size: usize,
+    last_used: u64,
+}
+
+/// Parsed objects kept in memory, up to `limit` bytes of object
+/// content. Once over the limit the least recently used objects are
+/// dropped, though the newest one is always kept, however large.
+pub struct ObjectCache {
+    limit: usize,
+    size: usize,
+    clock: u64,
+    entries: HashMap<String, CacheEntry>,
+    // Object IDs by the `clock` value they were last used at
+    recency: BTreeMap<u64, String>,
+}
+
+impl ObjectCache {
+    pub fn new(limit: usize) -> ObjectCache {
+        ObjectCache {
+            limit,
+            size: 0,
+            clock: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.evict();
+    }
+
+    pub fn contains(&self, oid: &str) -> bool {
+        self.entries.contains_key(oid)
+    }
+
+    /// Look up an object, marking it as the most recently used
+    pub fn get(&mut self, oid: &str) -> Option<&ParsedObject> {
+        self.clock += 1;
+        let clock = self.clock;
+
+        let entry = self.entries.get_mut(oid)?;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(clock, oid.to_string());
+        entry.last_used = clock;
+
+        Some(&entry.object)
+    }
+
+    pub fn insert(&mut self, oid: &str, object: ParsedObject, size: usize) {
+        self.clock += 1;
+
+        if let Some(old) = self.entries.remove(oid) {
+            self.recency.remove(&old.last_used);
+            self.size -= old.size;
+        }
+
+        self.entries.insert(
+            oid.to_string(),
+            CacheEntry {
+                object,
+                size,
+                last_used: self.clock,
+            },
+        );
+        self.recency.insert(self.clock, oid.to_string());
+        self.size += size;
+
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.limit && self.entries.len() > 1 {
+            let (&last_used, _) = self.recency.iter().next().unwrap();
+            let oid = self.recency.remove(&last_used).unwrap();
+            let entry = self.entries.remove(&oid).unwrap();
+            self.size -= entry.size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::blob::Blob;
+
+    fn insert_blob(cache: &mut ObjectCache, oid: &str, size: usize) {
+        cache.insert(oid, ParsedObject::Blob(Blob::new(&vec![0; size])), size);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_objects() {
+        let mut cache = ObjectCache::new(30);
+        insert_blob(&mut cache, "a", 10);
+        insert_blob(&mut cache, "b", 10);
+        insert_blob(&mut cache, "c", 10);
+
+        assert!(cache.get("a").is_some());
+        insert_blob(&mut cache, "d", 10);
+
+        assert!(cache.contains("a"));
+        assert!(!cache.contains("b"));
+        assert!(cache.contains("c"));
+        assert!(cache.contains("d"));
+
+        insert_blob(&mut cache, "e", 25);
+        assert!(!cache.contains("a"));
+        assert!(!cache.contains("c"));
+        assert!(!cache.contains("d"));
+        assert!(cache.contains("e"));
+
+        // Too big to fit, but kept until the next insert
+        insert_blob(&mut cache, "f", 40);
+        assert!(!cache.contains("e"));
+        assert!(cache.contains("f"));
+
+        cache.set_limit(0);
+        assert!(cache.contains("f"));
+        insert_blob(&mut cache, "g", 1);
+        assert!(!cache.contains("f"));
+        assert!(cache.contains("g"));
+    }
+}
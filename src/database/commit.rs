@@ -1,6 +1,5 @@
 use chrono::prelude::*;
-use std::collections::HashMap;
-use std::str;
+use std::fmt;
 
 use crate::database::{Object, ParsedObject};
 
@@ -9,16 +8,26 @@ pub struct Author {
     pub name: String,
     pub email: String,
     pub time: DateTime<FixedOffset>,
+    // The offset exactly as written, eg. `+0530`. Kept apart from
+    // `time` so that offsets git accepts but `FixedOffset` can't
+    // represent, or writes differently (`-0000`), survive a round trip
+    pub timezone: String,
+    // For an identity parsed from an object, the text as read and the
+    // text the fields above gave at the time. `parse` accepts lines it
+    // can't rebuild from the fields, so the text as read is written
+    // back for as long as the fields are unchanged.
+    raw: Option<(String, String)>,
 }
 
 impl Author {
-    fn to_string(&self) -> String {
-        format!(
-            "{} <{}> {}",
-            self.name,
-            self.email,
-            self.time.format("%s %z")
-        )
+    pub fn new(name: &str, email: &str, time: DateTime<FixedOffset>) -> Author {
+        Author {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+            timezone: time.format("%z").to_string(),
+            raw: None,
+        }
     }
 
     pub fn short_date(&self) -> String {
@@ -29,50 +38,172 @@ impl Author {
         self.time.format("%a %b %-d  %H:%M:%S %Y %Z").to_string()
     }
 
+    /// Parse `Name <email> <timestamp> <timezone>`. Malformed parts
+    /// are tolerated: a missing or invalid timestamp reads as the
+    /// epoch, and a timezone that isn't `+hhmm` or `-hhmm` as UTC.
     pub fn parse(s: &str) -> Author {
-        let split_author_str = s
-            .split(&['<', '>'][..])
-            .map(|s| s.trim())
-            .collect::<Vec<_>>();
+        let (name, rest) = match s.find('<') {
+            Some(open) => (s[..open].trim(), &s[open + 1..]),
+            None => (s.trim(), ""),
+        };
+        let (email, rest) = match rest.rfind('>') {
+            Some(close) => (&rest[..close], &rest[close + 1..]),
+            None => (rest, ""),
+        };
+
+        let mut date = rest.split_whitespace();
+        let timestamp = date.next().and_then(|t| t.parse().ok()).unwrap_or(0);
+        let timezone = date.next().unwrap_or("+0000").to_string();
+
+        let offset = Self::parse_offset(&timezone).unwrap_or_else(|| FixedOffset::east(0));
+        let time = offset
+            .timestamp_opt(timestamp, 0)
+            .single()
+            .unwrap_or_else(|| offset.timestamp(0, 0));
+
+        let mut author = Author {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+            timezone,
+            raw: None,
+        };
+        author.raw = Some((s.to_string(), author.write_fields()));
+        author
+    }
+
+    fn parse_offset(timezone: &str) -> Option<FixedOffset> {
+        if timezone.len() != 5 || !timezone[1..].chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let hours: i32 = timezone[1..3].parse().ok()?;
+        let minutes: i32 = timezone[3..5].parse().ok()?;
+        let seconds = hours * 3600 + minutes * 60;
+
+        match &timezone[0..1] {
+            "+" => FixedOffset::east_opt(seconds),
+            "-" => FixedOffset::west_opt(seconds),
+            _ => None,
+        }
+    }
+
+    fn write_fields(&self) -> String {
+        format!(
+            "{} <{}> {} {}",
+            self.name,
+            self.email,
+            self.time.timestamp(),
+            self.timezone
+        )
+    }
+}
+
+impl fmt::Display for Author {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line = self.write_fields();
+        match &self.raw {
+            Some((raw, parsed)) if *parsed == line => write!(f, "{}", raw),
+            _ => write!(f, "{}", line),
+        }
+    }
+}
 
-        let name = split_author_str[0].to_string();
-        let email = split_author_str[1].to_string();
-        let time = DateTime::parse_from_str(split_author_str[2], "%s %z")
-            .expect("could not parse datetime");
+/// How the text of a commit or tag is encoded, as named by its
+/// `encoding` header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextEncoding {
+    Utf8,
+    // Any encoding other than UTF-8 is read one byte per character,
+    // which keeps its bytes intact even when it isn't Latin-1
+    Latin1,
+}
 
-        Author { name, email, time }
+impl TextEncoding {
+    /// The encoding named by the `encoding` header in `headers`, or
+    /// UTF-8 when there is none
+    pub fn of(headers: &[(Vec<u8>, Vec<u8>)]) -> TextEncoding {
+        match headers.iter().find(|(key, _)| key == b"encoding") {
+            Some((_, name))
+                if !name.eq_ignore_ascii_case(b"utf-8") && !name.eq_ignore_ascii_case(b"utf8") =>
+            {
+                TextEncoding::Latin1
+            }
+            _ => TextEncoding::Utf8,
+        }
+    }
+
+    pub fn decode(self, data: &[u8]) -> String {
+        match self {
+            TextEncoding::Utf8 => String::from_utf8_lossy(data).into_owned(),
+            TextEncoding::Latin1 => data.iter().map(|&b| char::from(b)).collect(),
+        }
+    }
+
+    fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            TextEncoding::Utf8 => text.as_bytes().to_vec(),
+            TextEncoding::Latin1 => text
+                .chars()
+                .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+                .collect(),
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Commit {
-    pub parent: Option<String>,
+    pub parents: Vec<String>,
     pub tree_oid: String,
     pub author: Author,
+    pub committer: Author,
+    // Headers that rug has no use for, eg. `encoding`, `mergetag` or
+    // `gpgsig`, in their original order
+    pub extra_headers: Vec<(String, String)>,
     pub message: String,
+    pub text_encoding: TextEncoding,
+    // For a commit parsed from the database, the bytes it was read
+    // from and the bytes the fields above gave at the time. Missing or
+    // repeated headers, their order, and text that isn't valid in its
+    // encoding are only kept in the former, so they are written back
+    // for as long as the fields are unchanged.
+    raw: Option<(Vec<u8>, Vec<u8>)>,
 }
 
 impl Commit {
     pub fn new(
-        parent: &Option<String>,
+        parents: Vec<String>,
         tree_oid: String,
         author: Author,
+        committer: Author,
         message: String,
     ) -> Commit {
         Commit {
-            parent: parent.clone(),
+            parents,
             tree_oid,
             author,
+            committer,
+            extra_headers: vec![],
             message,
+            text_encoding: TextEncoding::Utf8,
+            raw: None,
         }
     }
 
     pub fn title_line(&self) -> String {
-        self.message
-            .lines()
-            .next()
-            .expect("could not get first line of commit")
-            .to_string()
+        self.message.lines().next().unwrap_or("").to_string()
+    }
+
+    fn write_fields(&self) -> Vec<u8> {
+        let mut headers = vec![("tree".to_string(), self.tree_oid.clone())];
+        for parent in &self.parents {
+            headers.push(("parent".to_string(), parent.clone()));
+        }
+        headers.push(("author".to_string(), self.author.to_string()));
+        headers.push(("committer".to_string(), self.committer.to_string()));
+        headers.extend(self.extra_headers.iter().cloned());
+
+        write_headers(&headers, &self.message, self.text_encoding)
     }
 }
 
@@ -82,47 +213,299 @@ impl Object for Commit {
     }
 
     fn to_string(&self) -> Vec<u8> {
-        let author_str = self.author.to_string();
-        let mut lines = String::new();
-        lines.push_str(&format!("tree {}\n", self.tree_oid));
-        if let Some(parent_oid) = &self.parent {
-            lines.push_str(&format!("parent {}\n", parent_oid));
+        let data = self.write_fields();
+        match &self.raw {
+            Some((raw, parsed)) if *parsed == data => raw.clone(),
+            _ => data,
         }
-        lines.push_str(&format!("author {}\n", author_str));
-        lines.push_str(&format!("committer {}\n", author_str));
-        lines.push_str("\n");
-        lines.push_str(&self.message);
-
-        lines.as_bytes().to_vec()
-    }
-
-    fn parse(s: &[u8]) -> ParsedObject {
-        let mut s = str::from_utf8(s).expect("invalid utf-8");
-        let mut headers = HashMap::new();
-        // Parse headers
-        loop {
-            if let Some(newline) = s.find('\n') {
-                let line = &s[..newline];
-                s = &s[newline + 1..];
-
-                // Headers and commit message is separated by empty
-                // line
-                if line == "" {
-                    break;
+    }
+
+    fn parse(s: &[u8]) -> Result<ParsedObject, String> {
+        let (headers, message) = parse_headers(s);
+        let text_encoding = TextEncoding::of(&headers);
+
+        let mut tree_oid = None;
+        let mut parents = vec![];
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers = vec![];
+
+        for (key, value) in &headers {
+            let key = String::from_utf8_lossy(key).into_owned();
+            match key.as_str() {
+                "tree" if tree_oid.is_none() => {
+                    tree_oid = Some(String::from_utf8_lossy(value).into_owned())
+                }
+                "parent" => parents.push(String::from_utf8_lossy(value).into_owned()),
+                "author" if author.is_none() => {
+                    author = Some(Author::parse(&text_encoding.decode(value)))
                 }
+                "committer" if committer.is_none() => {
+                    committer = Some(Author::parse(&text_encoding.decode(value)))
+                }
+                _ => extra_headers.push((key, text_encoding.decode(value))),
+            }
+        }
 
-                let v: Vec<&str> = line.splitn(2, ' ').collect();
-                headers.insert(v[0], v[1]);
-            } else {
-                panic!("no body in commit");
+        let author = author.unwrap_or_else(|| Author::parse(""));
+        let mut commit = Commit {
+            parents,
+            tree_oid: tree_oid.ok_or("no tree header")?,
+            committer: committer.unwrap_or_else(|| author.clone()),
+            author,
+            extra_headers,
+            message: text_encoding.decode(message),
+            text_encoding,
+            raw: None,
+        };
+        commit.raw = Some((s.to_vec(), commit.write_fields()));
+
+        Ok(ParsedObject::Commit(commit))
+    }
+}
+
+/// The keys and values of a commit's or tag's headers, in order
+pub type Headers = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Split a commit or tag into its headers and message, as the bytes
+/// they were written in. Lines starting with a space continue the
+/// header before them, as in a multi-line `gpgsig`; their values are
+/// joined with newlines.
+pub fn parse_headers(data: &[u8]) -> (Headers, &[u8]) {
+    let (header_block, message) = if data.first() == Some(&b'\n') {
+        (&data[..0], &data[1..])
+    } else {
+        match data.windows(2).position(|pair| pair == b"\n\n") {
+            Some(end) => (&data[..end], &data[end + 2..]),
+            None => {
+                let end = data.iter().rposition(|&b| b != b'\n').map_or(0, |i| i + 1);
+                (&data[..end], &data[data.len()..])
             }
         }
+    };
+
+    let mut headers: Headers = vec![];
+    let lines = header_block.split(|&b| b == b'\n');
+    for line in lines.filter(|line| !line.is_empty()) {
+        if let (Some(b' '), Some((_, value))) = (line.first(), headers.last_mut()) {
+            value.push(b'\n');
+            value.extend_from_slice(&line[1..]);
+            continue;
+        }
+
+        let mut parts = line.splitn(2, |&b| b == b' ');
+        let key = parts.next().unwrap_or(&[]).to_vec();
+        let value = parts.next().unwrap_or(&[]).to_vec();
+        headers.push((key, value));
+    }
+
+    (headers, message)
+}
+
+/// The inverse of `parse_headers`, for headers and a message that
+/// have been decoded with `text_encoding`
+pub fn write_headers(
+    headers: &[(String, String)],
+    message: &str,
+    text_encoding: TextEncoding,
+) -> Vec<u8> {
+    let mut lines = String::new();
+    for (key, value) in headers {
+        lines.push_str(key);
+        lines.push(' ');
+        lines.push_str(&value.replace('\n', "\n "));
+        lines.push('\n');
+    }
+    lines.push('\n');
+    lines.push_str(message);
+
+    text_encoding.encode(&lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_commit(data: &str) -> Commit {
+        match Commit::parse(data.as_bytes()) {
+            Ok(ParsedObject::Commit(commit)) => commit,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parses_merges_with_extra_headers() {
+        let data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+parent 1111111111111111111111111111111111111111
+parent 2222222222222222222222222222222222222222
+parent 3333333333333333333333333333333333333333
+author A. U. Thor <author@example.com> 1500000000 +0530
+committer C. O. Mitter <committer@example.com> 1500000100 -0000
+encoding ISO-8859-1
+gpgsig -----BEGIN PGP SIGNATURE-----
+ 
+ iQEzBAABCAAdFiEE
+ -----END PGP SIGNATURE-----
+
+Merge branches 'a' and 'b'
+
+Details
+";
+        let commit = parse_commit(data);
+
+        assert_eq!(
+            vec![
+                "1111111111111111111111111111111111111111",
+                "2222222222222222222222222222222222222222",
+                "3333333333333333333333333333333333333333",
+            ],
+            commit.parents
+        );
+        assert_eq!("A. U. Thor", commit.author.name);
+        assert_eq!("+0530", commit.author.timezone);
+        assert_eq!(
+            5 * 3600 + 30 * 60,
+            commit.author.time.offset().local_minus_utc()
+        );
+        assert_eq!("committer@example.com", commit.committer.email);
+        assert_eq!(1_500_000_100, commit.committer.time.timestamp());
+        assert_eq!("-0000", commit.committer.timezone);
+        assert_eq!(
+            vec![
+                ("encoding".to_string(), "ISO-8859-1".to_string()),
+                (
+                    "gpgsig".to_string(),
+                    "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----"
+                        .to_string()
+                ),
+            ],
+            commit.extra_headers
+        );
+        assert_eq!("Merge branches 'a' and 'b'\n\nDetails\n", commit.message);
+        assert_eq!("Merge branches 'a' and 'b'", commit.title_line());
+
+        assert_eq!(data.as_bytes().to_vec(), commit.to_string());
+    }
+
+    #[test]
+    fn round_trips_messages_in_other_encodings() {
+        let mut data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author Ren\xe9 <rene@example.com> 1500000000 +0100
+committer Ren\xe9 <rene@example.com> 1500000000 +0100
+encoding ISO-8859-1
+
+"
+        .to_vec();
+        data.extend_from_slice(b"caf\xe9\n");
+
+        let commit = match Commit::parse(&data) {
+            Ok(ParsedObject::Commit(commit)) => commit,
+            _ => unreachable!(),
+        };
+
+        assert_eq!("Ren\u{e9}", commit.author.name);
+        assert_eq!("caf\u{e9}", commit.title_line());
+        assert_eq!(data, commit.to_string());
+    }
+
+    #[test]
+    fn tolerates_malformed_identities() {
+        let data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author No Email 1500000000 +0000
+committer Odd Zone <odd@example.com> 1500000000 +05300
+
+";
+        let commit = parse_commit(data);
+
+        assert_eq!("No Email 1500000000 +0000", commit.author.name);
+        assert_eq!(0, commit.author.time.timestamp());
+        assert_eq!("No Email 1500000000 +0000", commit.author.to_string());
+        assert_eq!("+05300", commit.committer.timezone);
+        assert_eq!(0, commit.committer.time.offset().local_minus_utc());
+        assert_eq!("", commit.title_line());
+
+        assert_eq!(data.as_bytes().to_vec(), commit.to_string());
+    }
+
+    #[test]
+    fn keeps_headers_as_written() {
+        let data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+encoding UTF-8
+author A. U. Thor <author@example.com>    1500000000   +0000
+
+No committer
+";
+        let commit = parse_commit(data);
+
+        assert_eq!(1_500_000_000, commit.author.time.timestamp());
+        assert_eq!("A. U. Thor", commit.committer.name);
+        assert_eq!(
+            vec![("encoding".to_string(), "UTF-8".to_string())],
+            commit.extra_headers
+        );
+
+        assert_eq!(data.as_bytes().to_vec(), commit.to_string());
+    }
+
+    #[test]
+    fn decodes_only_the_message_when_it_is_not_utf8() {
+        let mut data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author Zo\u{eb} <zoe@example.com> 1500000000 +0000
+committer Zo\u{eb} <zoe@example.com> 1500000000 +0000
+
+"
+        .as_bytes()
+        .to_vec();
+        data.extend_from_slice(b"caf\xe9\n");
+        let commit = match Commit::parse(&data) {
+            Ok(ParsedObject::Commit(commit)) => commit,
+            _ => unreachable!(),
+        };
+
+        assert_eq!(TextEncoding::Utf8, commit.text_encoding);
+        assert_eq!("Zo\u{eb}", commit.author.name);
+        assert_eq!("caf\u{fffd}", commit.title_line());
+        assert_eq!(data, commit.to_string());
+    }
+
+    #[test]
+    fn writes_the_fields_once_they_change() {
+        let data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author No Email 1500000000 +0000
+committer C. O. Mitter <committer@example.com>   1500000000 +0000
+
+message
+";
+        let original = parse_commit(data);
+
+        let mut commit = original.clone();
+        commit.parents = vec!["1111111111111111111111111111111111111111".to_string()];
+        assert_eq!(
+            b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+parent 1111111111111111111111111111111111111111
+author No Email 1500000000 +0000
+committer C. O. Mitter <committer@example.com>   1500000000 +0000
+
+message
+"
+            .to_vec(),
+            commit.to_string()
+        );
+        assert_ne!(original.get_oid(), commit.get_oid());
+
+        let mut commit = original.clone();
+        commit.author.email = "none@example.com".to_string();
+        let data = String::from_utf8(commit.to_string()).unwrap();
+        assert!(data.contains("\nauthor No Email 1500000000 +0000 <none@example.com> 0 +0000\n"));
+    }
+
+    #[test]
+    fn rejects_commits_without_a_tree() {
+        let data = b"author A. U. Thor <author@example.com> 1500000000 +0000\n\nfirst\n";
 
-        ParsedObject::Commit(Commit::new(
-            &headers.get("parent").map(|s| s.to_string()),
-            headers.get("tree").expect("no tree header").to_string(),
-            Author::parse(headers.get("author").expect("no author found in commit")),
-            s.to_string(),
-        ))
+        assert_eq!(
+            Some("no tree header".to_string()),
+            Commit::parse(data).err()
+        );
     }
 }
@@ -0,0 +1,205 @@
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::database::commit::Commit;
+use crate::database::object::Object;
+use crate::lockfile::Lockfile;
+use crate::util::*;
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const VERSION: u8 = 1;
+const HASH_VERSION_SHA1: u8 = 1;
+const HEADER_SIZE: usize = 8;
+const CHUNK_LOOKUP_ENTRY_SIZE: usize = 12;
+
+const CHUNK_OID_FANOUT: &[u8; 4] = b"OIDF";
+const CHUNK_OID_LOOKUP: &[u8; 4] = b"OIDL";
+const CHUNK_COMMIT_DATA: &[u8; 4] = b"CDAT";
+const CHUNK_EXTRA_EDGES: &[u8; 4] = b"EDGE";
+
+const PARENT_NONE: u32 = 0x7000_0000;
+const EXTRA_EDGES_NEEDED: u32 = 0x8000_0000;
+const LAST_EDGE: u32 = 0x8000_0000;
+
+struct GraphCommit {
+    tree_oid: String,
+    parents: Vec<String>,
+    time: i64,
+}
+
+/// Builder for `objects/info/commit-graph`, which caches each
+/// commit's tree, parents, date and generation number so history
+/// walks don't need to inflate commit objects.
+#[derive(Default)]
+pub struct CommitGraph {
+    commits: BTreeMap<String, GraphCommit>,
+}
+
+impl CommitGraph {
+    pub fn new() -> CommitGraph {
+        CommitGraph::default()
+    }
+
+    pub fn add(&mut self, commit: &Commit) {
+        self.commits.insert(
+            commit.get_oid(),
+            GraphCommit {
+                tree_oid: commit.tree_oid.clone(),
+                parents: commit.parents.clone(),
+                time: commit.committer.time.timestamp(),
+            },
+        );
+    }
+
+    /// Write the graph to `path`. Every parent of an added commit
+    /// must have been added too.
+    pub fn write(&self, path: &Path) -> Result<(), io::Error> {
+        let positions: HashMap<&str, u32> = self
+            .commits
+            .keys()
+            .enumerate()
+            .map(|(i, oid)| (oid.as_str(), i as u32))
+            .collect();
+        let position = |oid: &str| {
+            positions.get(oid).cloned().ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("commit {} missing from commit-graph", oid),
+                )
+            })
+        };
+        let generations = self.generations();
+
+        let mut fanout = vec![];
+        for byte in 0..256 {
+            let count = self
+                .commits
+                .keys()
+                .take_while(|oid| usize::from_str_radix(&oid[0..2], 16).unwrap() <= byte)
+                .count();
+            fanout.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+
+        let mut lookup = vec![];
+        let mut data = vec![];
+        let mut edges: Vec<u32> = vec![];
+
+        for (oid, commit) in &self.commits {
+            lookup.extend(decode_hex(oid).expect("invalid oid"));
+            data.extend(decode_hex(&commit.tree_oid).expect("invalid oid"));
+
+            let first_parent = match commit.parents.first() {
+                Some(parent) => position(parent)?,
+                None => PARENT_NONE,
+            };
+            let second_parent = match commit.parents.len() {
+                0 | 1 => PARENT_NONE,
+                2 => position(&commit.parents[1])?,
+                _ => {
+                    let start = edges.len() as u32;
+                    for parent in &commit.parents[1..] {
+                        edges.push(position(parent)?);
+                    }
+                    *edges.last_mut().unwrap() |= LAST_EDGE;
+                    EXTRA_EDGES_NEEDED | start
+                }
+            };
+            data.extend_from_slice(&first_parent.to_be_bytes());
+            data.extend_from_slice(&second_parent.to_be_bytes());
+
+            // 30-bit generation number followed by a 34-bit date
+            let generation = generations[oid.as_str()];
+            let time = commit.time as u64;
+            data.extend_from_slice(
+                &((generation << 2) | ((time >> 32) as u32 & 0b11)).to_be_bytes(),
+            );
+            data.extend_from_slice(&(time as u32).to_be_bytes());
+        }
+
+        let mut chunks = vec![
+            (CHUNK_OID_FANOUT, fanout),
+            (CHUNK_OID_LOOKUP, lookup),
+            (CHUNK_COMMIT_DATA, data),
+        ];
+        if !edges.is_empty() {
+            let edges = edges
+                .iter()
+                .flat_map(|edge| edge.to_be_bytes().to_vec())
+                .collect();
+            chunks.push((CHUNK_EXTRA_EDGES, edges));
+        }
+
+        let mut graph = SIGNATURE.to_vec();
+        graph.extend_from_slice(&[VERSION, HASH_VERSION_SHA1, chunks.len() as u8, 0]);
+
+        let mut offset = HEADER_SIZE + (chunks.len() + 1) * CHUNK_LOOKUP_ENTRY_SIZE;
+        for (id, chunk) in &chunks {
+            graph.extend_from_slice(*id);
+            graph.extend_from_slice(&(offset as u64).to_be_bytes());
+            offset += chunk.len();
+        }
+        graph.extend_from_slice(&[0; 4]);
+        graph.extend_from_slice(&(offset as u64).to_be_bytes());
+
+        for (_, chunk) in chunks {
+            graph.extend(chunk);
+        }
+
+        let mut hasher = Sha1::new();
+        hasher.input(&graph);
+        graph.extend(decode_hex(&hasher.result_str()).unwrap());
+
+        fs::create_dir_all(path.parent().expect("invalid commit-graph path"))?;
+        let mut lock = Lockfile::new(path);
+        lock.hold_for_update()?;
+        lock.write_bytes(&graph)?;
+        lock.commit()
+    }
+
+    /// Topological levels: 1 for root commits, otherwise one more
+    /// than the highest parent
+    fn generations(&self) -> HashMap<&str, u32> {
+        let mut generations: HashMap<&str, u32> = HashMap::new();
+
+        for start in self.commits.keys() {
+            let mut stack = vec![start.as_str()];
+            while let Some(&oid) = stack.last() {
+                if generations.contains_key(oid) {
+                    stack.pop();
+                    continue;
+                }
+
+                let parents = self
+                    .commits
+                    .get(oid)
+                    .map(|commit| commit.parents.as_slice())
+                    .unwrap_or(&[]);
+                let pending: Vec<&str> = parents
+                    .iter()
+                    .map(|parent| parent.as_str())
+                    .filter(|parent| !generations.contains_key(parent))
+                    .filter(|parent| self.commits.contains_key(*parent))
+                    .collect();
+
+                if pending.is_empty() {
+                    let generation = parents
+                        .iter()
+                        .filter_map(|parent| generations.get(parent.as_str()))
+                        .max()
+                        .map(|max| max + 1)
+                        .unwrap_or(1);
+                    generations.insert(oid, generation);
+                    stack.pop();
+                } else {
+                    stack.extend(pending);
+                }
+            }
+        }
+
+        generations
+    }
+}
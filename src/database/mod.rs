@@ -1,32 +1,56 @@
+use std::cmp;
 use std::collections::HashMap;
-use std::fs::{self, OpenOptions};
-use std::io::prelude::*;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::str;
-
-use flate2::read::ZlibDecoder;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
+use std::time::SystemTime;
 
 use crate::index;
-use crate::util::*;
 
 pub mod blob;
+pub mod cache;
 pub mod commit;
+pub mod commit_graph;
 pub mod object;
+pub mod pack_index;
+pub mod store;
+pub mod tag;
 pub mod tree;
 pub mod tree_diff;
 
 use blob::Blob;
+use cache::{ObjectCache, DEFAULT_CACHE_LIMIT};
 use commit::Commit;
 use object::Object;
+use pack_index::PackIndex;
+use store::{LooseStore, ObjectStore};
+use tag::Tag;
 use tree::{Tree, TREE_MODE};
 
+/// Length abbreviated object IDs start at before being extended to
+/// stay unique
+const DEFAULT_ABBREV: usize = 7;
+
+/// Files that may sit beside a `.pack` in `objects/pack`
+const PACK_EXTENSIONS: [&str; 6] = ["pack", "idx", "keep", "bitmap", "promisor", "rev"];
+
+#[derive(Debug, Default)]
+pub struct ObjectCounts {
+    pub count: usize,
+    pub size: u64,
+    pub in_pack: usize,
+    pub packs: usize,
+    pub size_pack: u64,
+    pub prune_packable: usize,
+    pub garbage: usize,
+    pub size_garbage: u64,
+}
+
 #[derive(Debug)]
 pub enum ParsedObject {
     Commit(Commit),
     Blob(Blob),
     Tree(Tree),
+    Tag(Tag),
 }
 
 impl ParsedObject {
@@ -35,6 +59,7 @@ impl ParsedObject {
             ParsedObject::Commit(_) => "commit",
             ParsedObject::Blob(_) => "blob",
             ParsedObject::Tree(_) => "tree",
+            ParsedObject::Tag(_) => "tag",
         }
     }
 
@@ -43,6 +68,7 @@ impl ParsedObject {
             ParsedObject::Commit(obj) => obj.get_oid(),
             ParsedObject::Blob(obj) => obj.get_oid(),
             ParsedObject::Tree(obj) => obj.get_oid(),
+            ParsedObject::Tag(obj) => obj.get_oid(),
         }
     }
 }
@@ -93,70 +119,63 @@ impl Entry {
 
 pub struct Database {
     path: PathBuf,
-    objects: HashMap<String, ParsedObject>,
+    backend: Box<dyn ObjectStore>,
+    cache: ObjectCache,
 }
 
 impl Database {
     pub fn new(path: &Path) -> Database {
+        Database::with_store(path, Box::new(LooseStore::new(path)))
+    }
+
+    /// A database whose objects are read from and written to
+    /// `backend`. `path` is still used to find packs and the commit
+    /// graph.
+    pub fn with_store(path: &Path, backend: Box<dyn ObjectStore>) -> Database {
         Database {
             path: path.to_path_buf(),
-            objects: HashMap::new(),
+            backend,
+            cache: ObjectCache::new(DEFAULT_CACHE_LIMIT),
         }
     }
 
-    pub fn read_object(&self, oid: &str) -> Option<ParsedObject> {
-        let mut contents = vec![];
-        let mut file = OpenOptions::new()
-            .read(true)
-            .create(false)
-            .open(self.object_path(oid))
-            .unwrap_or_else(|_| panic!("failed to open file: {:?}", self.object_path(oid)));
-        file.read_to_end(&mut contents)
-            .expect("reading file failed");
-
-        let mut z = ZlibDecoder::new(&contents[..]);
-        let mut v = vec![];
-        z.read_to_end(&mut v).unwrap();
-        let mut vs = &v[..];
-
-        let (obj_type, rest) = match vs
-            .splitn(2, |c| *c as char == ' ')
-            .collect::<Vec<&[u8]>>()
-            .as_slice()
-        {
-            &[type_bytes, rest] => (
-                str::from_utf8(type_bytes).expect("failed to parse type"),
-                rest,
-            ),
-            _ => panic!("EOF while parsing type"),
-        };
-        vs = rest;
-
-        let (_size, rest) = match *vs
-            .splitn(2, |c| *c as char == '\u{0}')
-            .collect::<Vec<&[u8]>>()
-            .as_slice()
-        {
-            [size_bytes, rest] => (
-                str::from_utf8(size_bytes).expect("failed to parse size"),
-                rest,
-            ),
-            _ => panic!("EOF while parsing size"),
-        };
+    /// Bytes of object content to keep in memory between loads
+    pub fn set_cache_limit(&mut self, limit: usize) {
+        self.cache.set_limit(limit);
+    }
 
-        match obj_type {
-            "commit" => Some(Commit::parse(&rest)),
-            "blob" => Some(Blob::parse(&rest)),
-            "tree" => Some(Tree::parse(&rest)),
-            _ => unimplemented!(),
+    pub fn load(&mut self, oid: &str) -> &ParsedObject {
+        match self.try_load(oid) {
+            Ok(object) => object,
+            Err(error) => panic!("{}", error.trim_end()),
         }
     }
 
-    pub fn load(&mut self, oid: &str) -> &ParsedObject {
-        let o = self.read_object(oid);
-        self.objects.insert(oid.to_string(), o.unwrap());
+    /// Like `load`, but reports an object the store doesn't have, such
+    /// as one that is only in a pack, or can't parse as a fatal error
+    pub fn try_load(&mut self, oid: &str) -> Result<&ParsedObject, String> {
+        if !self.cache.contains(oid) {
+            match self.backend.read(oid) {
+                Ok(Some((object, size))) => self.cache.insert(oid, object, size),
+                Ok(None) => return Err(self.unreadable_object_error(oid)),
+                Err(error) => return Err(format!("fatal: bad object {}: {}\n", oid, error)),
+            }
+        }
+
+        self.cache
+            .get(oid)
+            .ok_or_else(|| format!("fatal: unable to read object {}\n", oid))
+    }
 
-        self.objects.get(oid).unwrap()
+    fn unreadable_object_error(&self, oid: &str) -> String {
+        if self.pack_indexes().iter().any(|index| index.contains(oid)) {
+            format!(
+                "fatal: object {} is packed; reading packs is not supported\n",
+                oid
+            )
+        } else {
+            format!("fatal: unable to read object {}\n", oid)
+        }
     }
 
     pub fn store<T>(&self, obj: &T) -> Result<(), std::io::Error>
@@ -166,75 +185,352 @@ impl Database {
         let oid = obj.get_oid();
         let content = obj.get_content();
 
-        self.write_object(oid, content)
+        self.backend.write(&oid, &content)
+    }
+
+    pub fn short_oid(oid: &str) -> &str {
+        &oid[0..6]
+    }
+
+    /// Shortest prefix of `oid`, at least `DEFAULT_ABBREV` characters
+    /// long, that no other object in the database shares
+    pub fn abbrev(&self, oid: &str) -> String {
+        let mut len = cmp::min(DEFAULT_ABBREV, oid.len());
+        let candidates = self.prefix_match(&oid[..len]);
+
+        while len < oid.len()
+            && candidates
+                .iter()
+                .any(|other| other != oid && other.starts_with(&oid[..len]))
+        {
+            len += 1;
+        }
+
+        oid[..len].to_string()
+    }
+
+    /// Find all object IDs starting with `name`, looking at both
+    /// the store and the indexes of any packfiles
+    pub fn prefix_match(&self, name: &str) -> Vec<String> {
+        let mut oids = self.backend.prefix_match(name);
+
+        for index in self.pack_indexes() {
+            for oid in index.prefix_match(name) {
+                if !oids.contains(&oid) {
+                    oids.push(oid);
+                }
+            }
+        }
+
+        oids
     }
 
-    fn object_path(&self, oid: &str) -> PathBuf {
-        let dir: &str = &oid[0..2];
-        let filename: &str = &oid[2..];
+    /// IDs of all objects in the store, which are the loose objects
+    /// for a `LooseStore`, sorted
+    pub fn loose_objects(&self) -> Vec<String> {
+        self.backend.list()
+    }
 
-        self.path.as_path().join(dir).join(filename)
+    /// When a loose object was written, or `None` if the store
+    /// doesn't have it
+    pub fn loose_mtime(&self, oid: &str) -> Option<SystemTime> {
+        self.backend.modified(oid)
     }
 
-    fn write_object(&self, oid: String, content: Vec<u8>) -> Result<(), std::io::Error> {
-        let object_path = self.object_path(&oid);
+    pub fn remove_loose(&self, oid: &str) -> Result<(), std::io::Error> {
+        self.backend.remove(oid)
+    }
 
-        // If object already exists, we are certain that the contents
-        // have not changed. So there is no need to write it again.
-        if object_path.exists() {
-            return Ok(());
+    /// Delete loose objects that are also stored in a pack, returning
+    /// the number removed. A pack index listing the object is enough
+    /// to know the loose copy is redundant.
+    pub fn prune_packed(&self) -> Result<usize, std::io::Error> {
+        let indexes = self.pack_indexes();
+        let mut pruned = 0;
+
+        for oid in self.loose_objects() {
+            if indexes.iter().any(|index| index.contains(&oid)) {
+                self.remove_loose(&oid)?;
+                pruned += 1;
+            }
         }
 
-        let dir_path = object_path.parent().expect("invalid parent path");
-        fs::create_dir_all(dir_path)?;
-        let mut temp_file_name = String::from("tmp_obj_");
-        temp_file_name.push_str(&generate_temp_name());
-        let temp_path = dir_path.join(temp_file_name);
+        Ok(pruned)
+    }
+
+    /// Tally loose objects, packs and stray files, in the manner of
+    /// `git count-objects -v`
+    pub fn count_objects(&self) -> Result<ObjectCounts, std::io::Error> {
+        let mut counts = ObjectCounts::default();
+        self.backend.count(&mut counts)?;
+
+        let indexes = self.pack_indexes();
+        counts.prune_packable = self
+            .loose_objects()
+            .iter()
+            .filter(|oid| indexes.iter().any(|index| index.contains(oid)))
+            .count();
+
+        self.count_packs(&mut counts)?;
 
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create_new(true)
-            .open(&temp_path)?;
+        Ok(counts)
+    }
+
+    /// A `.pack` and its `.idx` count as a pack. Either one alone, or
+    /// any file of an unknown kind, is garbage.
+    fn count_packs(&self, counts: &mut ObjectCounts) -> Result<(), std::io::Error> {
+        let files = match fs::read_dir(self.path.join("pack")) {
+            Ok(files) => files,
+            Err(_) => return Ok(()),
+        };
 
-        let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-        e.write_all(&content)?;
-        let compressed_bytes = e.finish()?;
+        // Like git, packs are measured by file length rather than
+        // disk usage
+        let mut stems: HashMap<String, Vec<(String, u64)>> = HashMap::new();
+        for file in files {
+            let file = file?;
+            let path = file.path();
+            let stat = file.metadata()?;
+            let size = stat.len();
+            match (path.file_stem(), path.extension()) {
+                (Some(stem), Some(ext)) if PACK_EXTENSIONS.contains(&&*ext.to_string_lossy()) => {
+                    stems
+                        .entry(stem.to_string_lossy().to_string())
+                        .or_default()
+                        .push((ext.to_string_lossy().to_string(), size));
+                }
+                _ => {
+                    counts.garbage += 1;
+                    counts.size_garbage += size;
+                }
+            }
+        }
+
+        for (stem, files) in stems {
+            let has = |ext: &str| files.iter().any(|(e, _)| e == ext);
+            let index = if has("pack") && has("idx") {
+                PackIndex::open(&self.path.join("pack").join(format!("{}.idx", stem))).ok()
+            } else {
+                None
+            };
+
+            match index {
+                Some(index) => {
+                    counts.packs += 1;
+                    counts.in_pack += index.count();
+                    counts.size_pack += files
+                        .iter()
+                        .filter(|(ext, _)| ext == "pack" || ext == "idx")
+                        .map(|(_, size)| size)
+                        .sum::<u64>();
+                }
+                None => {
+                    for (ext, size) in files {
+                        if ext == "pack" || ext == "idx" {
+                            counts.garbage += 1;
+                            counts.size_garbage += size;
+                        }
+                    }
+                }
+            }
+        }
 
-        file.write_all(&compressed_bytes)?;
-        fs::rename(temp_path, object_path)?;
         Ok(())
     }
 
-    pub fn short_oid(oid: &str) -> &str {
-        &oid[0..6]
+    pub fn commit_graph_path(&self) -> PathBuf {
+        self.path.join("info").join("commit-graph")
     }
 
-    pub fn prefix_match(&self, name: &str) -> Vec<String> {
-        let object_path = self.object_path(name);
-        let dirname = object_path
-            .parent()
-            .expect("Could not get parent from object_path");
-
-        let oids: Vec<_> = fs::read_dir(&dirname)
-            .expect("read_dir call failed")
-            .map(|f| {
-                format!(
-                    "{}{}",
-                    dirname
-                        .file_name()
-                        .expect("could not get filename")
-                        .to_str()
-                        .expect("conversion from OsStr to str failed"),
-                    f.unwrap()
-                        .file_name()
-                        .to_str()
-                        .expect("conversion from OsStr to str failed")
-                )
-            })
-            .filter(|o| o.starts_with(name))
+    pub fn pack_indexes(&self) -> Vec<PackIndex> {
+        let files = match fs::read_dir(self.path.join("pack")) {
+            Ok(files) => files,
+            Err(_) => return vec![],
+        };
+
+        files
+            .filter_map(|f| f.ok().map(|f| f.path()))
+            .filter(|path| path.extension().map(|ext| ext == "idx").unwrap_or(false))
+            .filter_map(|path| PackIndex::open(&path).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::generate_temp_name;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    /// Blobs kept in memory, counting how often the database asks
+    /// for one
+    struct FakeStore {
+        objects: RefCell<HashMap<String, Vec<u8>>>,
+        reads: Rc<Cell<usize>>,
+    }
+
+    impl ObjectStore for FakeStore {
+        fn read(&self, oid: &str) -> Result<Option<(ParsedObject, usize)>, String> {
+            self.reads.set(self.reads.get() + 1);
+            let objects = self.objects.borrow();
+            match objects.get(oid) {
+                Some(data) => Ok(Some((Blob::parse(data)?, data.len()))),
+                None => Ok(None),
+            }
+        }
+
+        fn write(&self, oid: &str, content: &[u8]) -> Result<(), std::io::Error> {
+            // Drop the `blob <size>\0` header
+            let data = content.splitn(2, |b| *b == 0).nth(1).unwrap();
+            self.objects
+                .borrow_mut()
+                .insert(oid.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn prefix_match(&self, prefix: &str) -> Vec<String> {
+            self.list()
+                .into_iter()
+                .filter(|oid| oid.starts_with(prefix))
+                .collect()
+        }
+
+        fn list(&self) -> Vec<String> {
+            let mut oids: Vec<_> = self.objects.borrow().keys().cloned().collect();
+            oids.sort();
+            oids
+        }
+
+        fn modified(&self, _oid: &str) -> Option<SystemTime> {
+            None
+        }
+
+        fn remove(&self, oid: &str) -> Result<(), std::io::Error> {
+            self.objects.borrow_mut().remove(oid);
+            Ok(())
+        }
+
+        fn count(&self, counts: &mut ObjectCounts) -> Result<(), std::io::Error> {
+            for data in self.objects.borrow().values() {
+                counts.count += 1;
+                counts.size += data.len() as u64;
+            }
+            Ok(())
+        }
+    }
+
+    fn blob_data(database: &mut Database, oid: &str) -> Vec<u8> {
+        match database.load(oid) {
+            ParsedObject::Blob(blob) => blob.data.clone(),
+            _ => panic!("expected a blob"),
+        }
+    }
+
+    #[test]
+    fn caches_loaded_objects() -> Result<(), std::io::Error> {
+        let reads = Rc::new(Cell::new(0));
+        let store = FakeStore {
+            objects: RefCell::new(HashMap::new()),
+            reads: reads.clone(),
+        };
+        let mut database = Database::with_store(Path::new("/nonexistent"), Box::new(store));
+
+        let first = Blob::new(b"first");
+        let second = Blob::new(b"second");
+        database.store(&first)?;
+        database.store(&second)?;
+
+        assert_eq!(b"first".to_vec(), blob_data(&mut database, &first.get_oid()));
+        assert_eq!(b"first".to_vec(), blob_data(&mut database, &first.get_oid()));
+        assert_eq!(b"second".to_vec(), blob_data(&mut database, &second.get_oid()));
+        assert_eq!(2, reads.get());
+
+        // Room for one object at a time, so the least recently used
+        // one goes
+        database.set_cache_limit(6);
+        assert_eq!(b"second".to_vec(), blob_data(&mut database, &second.get_oid()));
+        assert_eq!(2, reads.get());
+        assert_eq!(b"first".to_vec(), blob_data(&mut database, &first.get_oid()));
+        assert_eq!(b"second".to_vec(), blob_data(&mut database, &second.get_oid()));
+        assert_eq!(4, reads.get());
+
+        Ok(())
+    }
+
+    /// Times three passes loading every object, with and without the
+    /// cache, and checks that loading objects again is faster with
+    /// it. Not run by default, since timings depend on the machine:
+    ///
+    ///     cargo test --release -- --ignored repeated_loads
+    #[test]
+    #[ignore]
+    fn benchmark_repeated_loads() -> Result<(), std::io::Error> {
+        let mut temp_name = generate_temp_name();
+        temp_name.push_str("_rug_bench_objects");
+        let path = std::env::temp_dir().join(temp_name);
+
+        let blobs: Vec<_> = (0..2000)
+            .map(|n| Blob::new(format!("line {}\n", n).repeat(100).as_bytes()))
             .collect();
+        let oids: Vec<_> = blobs.iter().map(|blob| blob.get_oid()).collect();
+        let database = Database::new(&path);
+        for blob in &blobs {
+            database.store(blob)?;
+        }
 
-        oids
+        let mut timings = vec![];
+        for &limit in &[DEFAULT_CACHE_LIMIT, 0] {
+            let mut database = Database::new(&path);
+            database.set_cache_limit(limit);
+
+            let passes: Vec<_> = (0..3)
+                .map(|_| {
+                    let start = Instant::now();
+                    for oid in &oids {
+                        database.load(oid);
+                    }
+                    start.elapsed()
+                })
+                .collect();
+            timings.push(passes);
+        }
+        fs::remove_dir_all(&path)?;
+
+        let (cached, uncached) = (&timings[0], &timings[1]);
+        assert!(
+            cached[1..].iter().max() < uncached.iter().min(),
+            "loading {} objects took {:?} with the cache and {:?} without",
+            oids.len(),
+            cached,
+            uncached
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn looks_objects_up_in_the_store() -> Result<(), std::io::Error> {
+        let store = FakeStore {
+            objects: RefCell::new(HashMap::new()),
+            reads: Rc::new(Cell::new(0)),
+        };
+        let database = Database::with_store(Path::new("/nonexistent"), Box::new(store));
+
+        let blob = Blob::new(b"first");
+        let oid = blob.get_oid();
+        database.store(&blob)?;
+
+        assert_eq!(vec![oid.clone()], database.prefix_match(&oid[0..4]));
+        assert_eq!(oid[0..7].to_string(), database.abbrev(&oid));
+        assert_eq!(vec![oid.clone()], database.loose_objects());
+        assert_eq!(1, database.count_objects()?.count);
+
+        database.remove_loose(&oid)?;
+        assert!(database.prefix_match(&oid[0..4]).is_empty());
+        assert_eq!(0, database.count_objects()?.count);
+
+        Ok(())
     }
 }
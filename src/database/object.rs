@@ -6,7 +6,8 @@ pub trait Object {
     fn r#type(&self) -> String;
     fn to_string(&self) -> Vec<u8>;
 
-    fn parse(s: &[u8]) -> ParsedObject;
+    /// Parse an object's content, or describe what is malformed
+    fn parse(s: &[u8]) -> Result<ParsedObject, String>;
 
     fn get_oid(&self) -> String {
         let mut hasher = Sha1::new();
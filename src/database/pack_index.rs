@@ -0,0 +1,178 @@
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::util::*;
+
+const IDX_SIGNATURE: [u8; 4] = [0xff, b't', b'O', b'c'];
+const FANOUT_SIZE: usize = 256 * 4;
+const OID_SIZE: usize = 20;
+
+/// Reader for the `.idx` file that accompanies a packfile. Only the
+/// sorted table of object IDs is used, to answer prefix queries.
+pub struct PackIndex {
+    data: Vec<u8>,
+    fanout_offset: usize,
+    table_offset: usize,
+    // Distance between consecutive object IDs in the table, and where
+    // the ID sits inside each record. Version 1 indexes interleave a
+    // 4-byte pack offset with each ID; version 2 keeps IDs contiguous.
+    entry_size: usize,
+    oid_offset: usize,
+}
+
+impl PackIndex {
+    pub fn open(path: &Path) -> Result<PackIndex, io::Error> {
+        let data = fs::read(path)?;
+
+        let index = if data.starts_with(&IDX_SIGNATURE) {
+            let version = u32::from_be_bytes(Self::slice(&data, 4, 4)?.try_into().unwrap());
+            if version != 2 {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unsupported pack index version {}", version),
+                ));
+            }
+            PackIndex {
+                data,
+                fanout_offset: 8,
+                table_offset: 8 + FANOUT_SIZE,
+                entry_size: OID_SIZE,
+                oid_offset: 0,
+            }
+        } else {
+            PackIndex {
+                data,
+                fanout_offset: 0,
+                table_offset: FANOUT_SIZE,
+                entry_size: 4 + OID_SIZE,
+                oid_offset: 4,
+            }
+        };
+
+        Self::slice(&index.data, index.fanout_offset, FANOUT_SIZE)?;
+        Self::slice(&index.data, index.table_offset, index.count() * index.entry_size)?;
+
+        Ok(index)
+    }
+
+    fn slice(data: &[u8], start: usize, len: usize) -> Result<&[u8], io::Error> {
+        data.get(start..start + len)
+            .ok_or_else(|| io::Error::new(ErrorKind::UnexpectedEof, "truncated pack index"))
+    }
+
+    /// Number of objects whose first byte is less than or equal to
+    /// `byte`
+    fn fanout(&self, byte: usize) -> usize {
+        let start = self.fanout_offset + byte * 4;
+        u32::from_be_bytes(self.data[start..start + 4].try_into().unwrap()) as usize
+    }
+
+    /// Number of objects in the pack
+    pub fn count(&self) -> usize {
+        self.fanout(255)
+    }
+
+    pub fn contains(&self, oid: &str) -> bool {
+        self.prefix_match(oid).iter().any(|other| other == oid)
+    }
+
+    fn oid_at(&self, position: usize) -> String {
+        let start = self.table_offset + position * self.entry_size + self.oid_offset;
+        encode_hex(&self.data[start..start + OID_SIZE])
+    }
+
+    /// All object IDs in the pack that start with the hex string
+    /// `prefix`. The fan-out table narrows the search to IDs sharing
+    /// the prefix's first byte.
+    pub fn prefix_match(&self, prefix: &str) -> Vec<String> {
+        let first_byte = match prefix.get(0..2).map(|s| usize::from_str_radix(s, 16)) {
+            Some(Ok(byte)) => byte,
+            _ => return vec![],
+        };
+
+        let start = if first_byte == 0 {
+            0
+        } else {
+            self.fanout(first_byte - 1)
+        };
+        let end = self.fanout(first_byte);
+
+        (start..end)
+            .map(|position| self.oid_at(position))
+            .filter(|oid| oid.starts_with(prefix))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    fn write_v2_index(path: &Path, oids: &[&str]) -> Result<(), io::Error> {
+        File::create(path)?.write_all(&v2_index(oids))
+    }
+
+    /// A version 2 index listing `oids`, without offsets or checksums
+    pub fn v2_index(oids: &[&str]) -> Vec<u8> {
+        let mut oids = oids.to_vec();
+        oids.sort();
+
+        let mut data = IDX_SIGNATURE.to_vec();
+        data.extend_from_slice(&2u32.to_be_bytes());
+        for byte in 0..256 {
+            let count = oids
+                .iter()
+                .filter(|oid| usize::from_str_radix(&oid[0..2], 16).unwrap() <= byte)
+                .count();
+            data.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+        for oid in oids {
+            data.extend_from_slice(&decode_hex(oid).unwrap());
+        }
+
+        data
+    }
+
+    #[test]
+    fn finds_object_ids_by_prefix() -> Result<(), io::Error> {
+        let mut temp_name = generate_temp_name();
+        temp_name.push_str("_rug_test.idx");
+        let path = std::env::temp_dir().join(temp_name);
+
+        write_v2_index(
+            &path,
+            &[
+                "1234567890123456789012345678901234567890",
+                "1234500000000000000000000000000000000000",
+                "abcdef0000000000000000000000000000000000",
+                "ff00000000000000000000000000000000000000",
+            ],
+        )?;
+        let index = PackIndex::open(&path)?;
+        fs::remove_file(&path)?;
+
+        assert_eq!(4, index.count());
+        assert_eq!(
+            vec![
+                "1234500000000000000000000000000000000000",
+                "1234567890123456789012345678901234567890"
+            ],
+            index.prefix_match("12345")
+        );
+        assert_eq!(
+            vec!["1234567890123456789012345678901234567890"],
+            index.prefix_match("123456")
+        );
+        assert_eq!(
+            vec!["ff00000000000000000000000000000000000000"],
+            index.prefix_match("ff0")
+        );
+        assert!(index.prefix_match("abcdef1").is_empty());
+
+        Ok(())
+    }
+}
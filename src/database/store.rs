@@ -0,0 +1,291 @@
+use std::fs::{self, OpenOptions};
+use std::io::prelude::*;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::time::SystemTime;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::database::blob::Blob;
+use crate::database::commit::Commit;
+use crate::database::object::Object;
+use crate::database::tag::Tag;
+use crate::database::tree::Tree;
+use crate::database::{ObjectCounts, ParsedObject};
+use crate::util::*;
+
+/// Where the database reads objects from and writes them to. The
+/// database caches what it reads, so a store is asked for each object
+/// at most once while it stays cached.
+pub trait ObjectStore {
+    /// Parse the object with the given ID, returning it along with
+    /// its size in bytes, or `None` if the store doesn't have it. An
+    /// object the store has but can't parse is an error.
+    fn read(&self, oid: &str) -> Result<Option<(ParsedObject, usize)>, String>;
+
+    /// Store `content`, a serialized object including its header,
+    /// under `oid`
+    fn write(&self, oid: &str, content: &[u8]) -> Result<(), std::io::Error>;
+
+    /// IDs of the objects in the store that start with `prefix`
+    fn prefix_match(&self, prefix: &str) -> Vec<String>;
+
+    /// IDs of all objects in the store, sorted
+    fn list(&self) -> Vec<String>;
+
+    /// When the object was written, or `None` if the store doesn't
+    /// have it
+    fn modified(&self, oid: &str) -> Option<SystemTime>;
+
+    fn remove(&self, oid: &str) -> Result<(), std::io::Error>;
+
+    /// Add the number and size of the store's objects, and of any
+    /// files in it that aren't objects, to `counts`
+    fn count(&self, counts: &mut ObjectCounts) -> Result<(), std::io::Error>;
+}
+
+/// Zlib-compressed loose objects under `.git/objects`
+pub struct LooseStore {
+    path: PathBuf,
+}
+
+impl LooseStore {
+    pub fn new(path: &Path) -> LooseStore {
+        LooseStore {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl ObjectStore for LooseStore {
+    fn read(&self, oid: &str) -> Result<Option<(ParsedObject, usize)>, String> {
+        let contents = match fs::read(object_path(&self.path, oid)) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(None),
+        };
+
+        let mut z = ZlibDecoder::new(&contents[..]);
+        let mut v = vec![];
+        z.read_to_end(&mut v)
+            .map_err(|e| format!("failed to decompress: {}", e))?;
+        let mut vs = &v[..];
+
+        let (obj_type, rest) = match vs
+            .splitn(2, |c| *c as char == ' ')
+            .collect::<Vec<&[u8]>>()
+            .as_slice()
+        {
+            &[type_bytes, rest] => (
+                str::from_utf8(type_bytes).map_err(|_| "failed to parse type")?,
+                rest,
+            ),
+            _ => return Err("EOF while parsing type".to_string()),
+        };
+        vs = rest;
+
+        let (_size, rest) = match *vs
+            .splitn(2, |c| *c as char == '\u{0}')
+            .collect::<Vec<&[u8]>>()
+            .as_slice()
+        {
+            [size_bytes, rest] => (
+                str::from_utf8(size_bytes)
+                    .ok()
+                    .and_then(|size| size.parse::<usize>().ok())
+                    .ok_or("failed to parse size")?,
+                rest,
+            ),
+            _ => return Err("EOF while parsing size".to_string()),
+        };
+
+        let object = match obj_type {
+            "commit" => Commit::parse(rest)?,
+            "blob" => Blob::parse(rest)?,
+            "tree" => Tree::parse(rest)?,
+            "tag" => Tag::parse(rest)?,
+            _ => return Err(format!("unknown object type '{}'", obj_type)),
+        };
+
+        Ok(Some((object, rest.len())))
+    }
+
+    fn write(&self, oid: &str, content: &[u8]) -> Result<(), std::io::Error> {
+        let object_path = object_path(&self.path, oid);
+
+        // If object already exists, we are certain that the contents
+        // have not changed. So there is no need to write it again.
+        if object_path.exists() {
+            return Ok(());
+        }
+
+        let dir_path = object_path.parent().expect("invalid parent path");
+        fs::create_dir_all(dir_path)?;
+        let mut temp_file_name = String::from("tmp_obj_");
+        temp_file_name.push_str(&generate_temp_name());
+        let temp_path = dir_path.join(temp_file_name);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(content)?;
+        let compressed_bytes = e.finish()?;
+
+        file.write_all(&compressed_bytes)?;
+        fs::rename(temp_path, object_path)?;
+        Ok(())
+    }
+
+    fn prefix_match(&self, prefix: &str) -> Vec<String> {
+        if prefix.len() < 2 {
+            return vec![];
+        }
+
+        let files = match fs::read_dir(self.path.join(&prefix[0..2])) {
+            Ok(files) => files,
+            Err(_) => return vec![],
+        };
+
+        files
+            .flatten()
+            .map(|file| format!("{}{}", &prefix[0..2], file.file_name().to_string_lossy()))
+            .filter(|oid| oid.starts_with(prefix) && is_loose_file_name(&oid[2..]))
+            .collect()
+    }
+
+    fn list(&self) -> Vec<String> {
+        let mut oids = vec![];
+        let dirs = match fs::read_dir(&self.path) {
+            Ok(dirs) => dirs,
+            Err(_) => return oids,
+        };
+
+        for dir in dirs.filter_map(|d| d.ok()) {
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            if !is_loose_dir_name(&prefix) {
+                continue;
+            }
+
+            for file in fs::read_dir(dir.path()).into_iter().flatten().flatten() {
+                let rest = file.file_name().to_string_lossy().to_string();
+                if is_loose_file_name(&rest) {
+                    oids.push(format!("{}{}", prefix, rest));
+                }
+            }
+        }
+
+        oids.sort();
+        oids
+    }
+
+    fn modified(&self, oid: &str) -> Option<SystemTime> {
+        fs::metadata(object_path(&self.path, oid))
+            .and_then(|stat| stat.modified())
+            .ok()
+    }
+
+    /// Delete a loose object, and its fan-out directory if that is
+    /// left empty
+    fn remove(&self, oid: &str) -> Result<(), std::io::Error> {
+        let path = object_path(&self.path, oid);
+        fs::remove_file(&path)?;
+        let _ = fs::remove_dir(path.parent().unwrap());
+
+        Ok(())
+    }
+
+    /// Sizes are disk usage in bytes, as `git count-objects` reports
+    fn count(&self, counts: &mut ObjectCounts) -> Result<(), std::io::Error> {
+        let dirs = match fs::read_dir(&self.path) {
+            Ok(dirs) => dirs,
+            Err(_) => return Ok(()),
+        };
+
+        for dir in dirs {
+            let dir = dir?;
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            if !is_loose_dir_name(&prefix) || !dir.path().is_dir() {
+                continue;
+            }
+
+            for file in fs::read_dir(dir.path())? {
+                let file = file?;
+                let rest = file.file_name().to_string_lossy().to_string();
+                let size = file.metadata()?.blocks() * 512;
+
+                if is_loose_file_name(&rest) {
+                    counts.count += 1;
+                    counts.size += size;
+                } else {
+                    counts.garbage += 1;
+                    counts.size_garbage += size;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Path of a loose object within the objects directory `path`
+pub fn object_path(path: &Path, oid: &str) -> PathBuf {
+    let dir: &str = &oid[0..2];
+    let filename: &str = &oid[2..];
+
+    path.join(dir).join(filename)
+}
+
+fn is_loose_dir_name(name: &str) -> bool {
+    name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_loose_file_name(name: &str) -> bool {
+    name.len() == 38 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read an object whose file holds `file`, returning the error
+    fn read_error(file: &[u8]) -> String {
+        let mut temp_name = generate_temp_name();
+        temp_name.push_str("_rug_store_objects");
+        let path = std::env::temp_dir().join(temp_name);
+        let oid = "1".repeat(40);
+
+        fs::create_dir_all(path.join(&oid[0..2])).unwrap();
+        fs::write(object_path(&path, &oid), file).unwrap();
+        let error = LooseStore::new(&path).read(&oid).map(|_| ()).unwrap_err();
+
+        fs::remove_dir_all(&path).unwrap();
+        error
+    }
+
+    fn compress(content: &[u8]) -> Vec<u8> {
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(content).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn reports_corrupt_objects() {
+        assert!(read_error(b"not zlib").starts_with("failed to decompress: "));
+        assert_eq!("EOF while parsing type", read_error(&compress(b"blob")));
+        assert_eq!("EOF while parsing size", read_error(&compress(b"blob 4")));
+        assert_eq!(
+            "failed to parse size",
+            read_error(&compress(b"blob four\0data"))
+        );
+        assert_eq!(
+            "unknown object type 'note'",
+            read_error(&compress(b"note 4\0data"))
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::database::commit::{parse_headers, write_headers, Author, TextEncoding};
+use crate::database::{Object, ParsedObject};
+
+/// An annotated tag, pointing at `object` of type `target_type`
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub object: String,
+    pub target_type: String,
+    pub name: String,
+    // Very old tags were written without a tagger
+    pub tagger: Option<Author>,
+    pub extra_headers: Vec<(String, String)>,
+    pub message: String,
+    pub text_encoding: TextEncoding,
+    // The bytes a parsed tag was read from, and those its fields gave
+    // at the time, as for `Commit`
+    raw: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Tag {
+    pub fn new(
+        object: &str,
+        target_type: &str,
+        name: &str,
+        tagger: Option<Author>,
+        message: &str,
+    ) -> Tag {
+        Tag {
+            object: object.to_string(),
+            target_type: target_type.to_string(),
+            name: name.to_string(),
+            tagger,
+            extra_headers: vec![],
+            message: message.to_string(),
+            text_encoding: TextEncoding::Utf8,
+            raw: None,
+        }
+    }
+
+    fn write_fields(&self) -> Vec<u8> {
+        let mut headers = vec![
+            ("object".to_string(), self.object.clone()),
+            ("type".to_string(), self.target_type.clone()),
+            ("tag".to_string(), self.name.clone()),
+        ];
+        if let Some(tagger) = &self.tagger {
+            headers.push(("tagger".to_string(), tagger.to_string()));
+        }
+        headers.extend(self.extra_headers.iter().cloned());
+
+        write_headers(&headers, &self.message, self.text_encoding)
+    }
+}
+
+impl Object for Tag {
+    fn r#type(&self) -> String {
+        "tag".to_string()
+    }
+
+    fn to_string(&self) -> Vec<u8> {
+        let data = self.write_fields();
+        match &self.raw {
+            Some((raw, parsed)) if *parsed == data => raw.clone(),
+            _ => data,
+        }
+    }
+
+    fn parse(s: &[u8]) -> Result<ParsedObject, String> {
+        let (headers, message) = parse_headers(s);
+        let text_encoding = TextEncoding::of(&headers);
+
+        let mut object = None;
+        let mut target_type = None;
+        let mut name = None;
+        let mut tagger = None;
+        let mut extra_headers = vec![];
+
+        for (key, value) in &headers {
+            let key = String::from_utf8_lossy(key).into_owned();
+            match key.as_str() {
+                "object" if object.is_none() => {
+                    object = Some(String::from_utf8_lossy(value).into_owned())
+                }
+                "type" if target_type.is_none() => {
+                    target_type = Some(String::from_utf8_lossy(value).into_owned())
+                }
+                "tag" if name.is_none() => name = Some(text_encoding.decode(value)),
+                "tagger" if tagger.is_none() => {
+                    tagger = Some(Author::parse(&text_encoding.decode(value)))
+                }
+                _ => extra_headers.push((key, text_encoding.decode(value))),
+            }
+        }
+
+        let mut tag = Tag {
+            object: object.ok_or("no object header")?,
+            target_type: target_type.unwrap_or_default(),
+            name: name.unwrap_or_default(),
+            tagger,
+            extra_headers,
+            message: text_encoding.decode(message),
+            text_encoding,
+            raw: None,
+        };
+        tag.raw = Some((s.to_vec(), tag.write_fields()));
+
+        Ok(ParsedObject::Tag(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_tags() {
+        let data = "object 1111111111111111111111111111111111111111
+type commit
+tag v1.0
+tagger A. U. Thor <author@example.com> 1500000000 -0700
+
+Version 1.0
+-----BEGIN PGP SIGNATURE-----
+-----END PGP SIGNATURE-----
+";
+        let tag = match Tag::parse(data.as_bytes()) {
+            Ok(ParsedObject::Tag(tag)) => tag,
+            _ => unreachable!(),
+        };
+
+        assert_eq!("1111111111111111111111111111111111111111", tag.object);
+        assert_eq!("commit", tag.target_type);
+        assert_eq!("v1.0", tag.name);
+        assert_eq!("-0700", tag.tagger.as_ref().unwrap().timezone);
+        assert!(tag.message.starts_with("Version 1.0\n"));
+
+        assert_eq!(data.as_bytes().to_vec(), tag.to_string());
+    }
+
+    #[test]
+    fn keeps_headers_as_written() {
+        let data = "object 1111111111111111111111111111111111111111
+tag v1.0
+type commit
+tagger Nobody 1500000000
+
+Version 1.0
+";
+        let tag = match Tag::parse(data.as_bytes()) {
+            Ok(ParsedObject::Tag(tag)) => tag,
+            _ => unreachable!(),
+        };
+
+        let tagger = tag.tagger.as_ref().unwrap();
+        assert_eq!("Nobody 1500000000", tagger.name);
+        assert_eq!("Nobody 1500000000", tagger.to_string());
+
+        assert_eq!(data.as_bytes().to_vec(), tag.to_string());
+    }
+
+    #[test]
+    fn writes_the_fields_once_they_change() {
+        let data = "object 1111111111111111111111111111111111111111
+tag v1.0
+type commit
+
+Version 1.0
+";
+        let mut tag = match Tag::parse(data.as_bytes()) {
+            Ok(ParsedObject::Tag(tag)) => tag,
+            _ => unreachable!(),
+        };
+
+        tag.object = "2222222222222222222222222222222222222222".to_string();
+        assert_eq!(
+            b"object 2222222222222222222222222222222222222222
+type commit
+tag v1.0
+
+Version 1.0
+"
+            .to_vec(),
+            tag.to_string()
+        );
+    }
+}
@@ -110,7 +110,7 @@ impl Object for Tree {
         tree_vec
     }
 
-    fn parse(v: &[u8]) -> ParsedObject {
+    fn parse(v: &[u8]) -> Result<ParsedObject, String> {
         let mut entries: Vec<Entry> = vec![];
 
         let mut vs = v;
@@ -147,6 +147,6 @@ impl Object for Tree {
 
             entries.push(Entry::new(name, &oid, mode));
         }
-        ParsedObject::Tree(Tree::build(&entries))
+        Ok(ParsedObject::Tree(Tree::build(&entries)))
     }
 }
@@ -13,10 +13,17 @@ use crate::lockfile::Lockfile;
 use crate::util::*;
 
 const MAX_PATH_SIZE: u16 = 0xfff;
+const ASSUME_VALID_FLAG: u16 = 0x8000;
+const EXTENDED_FLAG: u16 = 0x4000;
+const SKIP_WORKTREE_FLAG: u16 = 0x4000;
 const CHECKSUM_SIZE: u64 = 20;
 
 const HEADER_SIZE: usize = 12; // bytes
 const MIN_ENTRY_SIZE: usize = 64;
+const ENTRY_PATH_OFFSET: usize = 62;
+// Version 3 entries with extended flags carry two more bytes
+// before the path
+const EXTENDED_ENTRY_PATH_OFFSET: usize = 64;
 
 #[derive(Debug, Clone)]
 pub struct Entry {
@@ -30,6 +37,7 @@ pub struct Entry {
     gid: u32,
     size: u64,
     flags: u16,
+    flags_extended: u16,
     pub mode: u32,
     pub oid: String,
     pub path: String,
@@ -63,6 +71,7 @@ impl Entry {
             size: metadata.size(),
             oid: oid.to_string(),
             flags: cmp::min(path.len() as u16, MAX_PATH_SIZE),
+            flags_extended: 0,
             path,
         }
     }
@@ -77,7 +86,15 @@ impl Entry {
 
         let oid = encode_hex(&bytes[40..60]);
         let flags = u16::from_be_bytes(bytes[60..62].try_into().unwrap());
-        let path_bytes = bytes[62..].split(|b| b == &0u8).next().unwrap();
+        let flags_extended = if flags & EXTENDED_FLAG != 0 {
+            u16::from_be_bytes(bytes[62..64].try_into().unwrap())
+        } else {
+            0
+        };
+        let path_bytes = bytes[Entry::path_offset(flags)..]
+            .split(|b| b == &0u8)
+            .next()
+            .unwrap();
         let path = str::from_utf8(path_bytes).unwrap().to_string();
 
         Ok(Entry {
@@ -94,10 +111,19 @@ impl Entry {
 
             oid,
             flags,
+            flags_extended,
             path,
         })
     }
 
+    fn path_offset(flags: u16) -> usize {
+        if flags & EXTENDED_FLAG != 0 {
+            EXTENDED_ENTRY_PATH_OFFSET
+        } else {
+            ENTRY_PATH_OFFSET
+        }
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
         // 10 32-bit integers
@@ -117,6 +143,9 @@ impl Entry {
 
         // 16-bit
         bytes.extend_from_slice(&self.flags.to_be_bytes());
+        if self.is_extended() {
+            bytes.extend_from_slice(&self.flags_extended.to_be_bytes());
+        }
 
         bytes.extend_from_slice(self.path.as_bytes());
         bytes.push(0x0);
@@ -142,6 +171,50 @@ impl Entry {
         parent_dirs
     }
 
+    fn is_extended(&self) -> bool {
+        self.flags & EXTENDED_FLAG != 0
+    }
+
+    /// Set by `update-index --assume-unchanged`: the workspace file is
+    /// taken to match the index without looking at it
+    pub fn is_assume_unchanged(&self) -> bool {
+        self.flags & ASSUME_VALID_FLAG != 0
+    }
+
+    fn set_assume_unchanged(&mut self, value: bool) {
+        if value {
+            self.flags |= ASSUME_VALID_FLAG;
+        } else {
+            self.flags &= !ASSUME_VALID_FLAG;
+        }
+    }
+
+    /// Set by `update-index --skip-worktree`: the workspace copy is
+    /// ignored, whether or not it exists
+    pub fn is_skip_worktree(&self) -> bool {
+        self.flags_extended & SKIP_WORKTREE_FLAG != 0
+    }
+
+    fn set_skip_worktree(&mut self, value: bool) {
+        if value {
+            self.flags_extended |= SKIP_WORKTREE_FLAG;
+        } else {
+            self.flags_extended &= !SKIP_WORKTREE_FLAG;
+        }
+
+        if self.flags_extended != 0 {
+            self.flags |= EXTENDED_FLAG;
+        } else {
+            self.flags &= !EXTENDED_FLAG;
+        }
+    }
+
+    /// Whether status and add should leave the workspace copy of
+    /// this entry unexamined
+    pub fn skips_workspace_checks(&self) -> bool {
+        self.is_assume_unchanged() || self.is_skip_worktree()
+    }
+
     pub fn stat_match(&self, stat: &fs::Metadata) -> bool {
         (self.mode == Entry::mode(stat.mode())) && (self.size == 0 || self.size == stat.size())
     }
@@ -256,9 +329,17 @@ impl Index {
         let lock = &mut self.lockfile;
         let mut writer: Checksum<&Lockfile> = Checksum::new(lock);
 
+        // Extended flags need version 3, which git only writes when
+        // some entry uses them
+        let version: u32 = if self.entries.values().any(Entry::is_extended) {
+            3
+        } else {
+            2
+        };
+
         let mut header_bytes: Vec<u8> = vec![];
         header_bytes.extend_from_slice(b"DIRC");
-        header_bytes.extend_from_slice(&2u32.to_be_bytes()); // version no.
+        header_bytes.extend_from_slice(&version.to_be_bytes());
         header_bytes.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
         writer.write(&header_bytes)?;
         for (_key, entry) in self.entries.iter() {
@@ -322,6 +403,44 @@ impl Index {
         self.changed = true;
     }
 
+    /// Move the entry at `source`, or every entry beneath it if
+    /// `source` is a directory, to `destination`. Cached stat data is
+    /// kept since renaming does not change file contents.
+    pub fn rename(&mut self, source: &str, destination: &str) {
+        let mut paths = vec![];
+        if self.is_tracked_file(source) {
+            paths.push(source.to_string());
+        }
+        if let Some(children) = self.parents.get(source) {
+            paths.extend(children.iter().cloned());
+        }
+
+        for path in paths {
+            if let Some(mut entry) = self.entries.get(&path).cloned() {
+                self.remove_entry(&path);
+
+                let rest = Path::new(&path)
+                    .strip_prefix(source)
+                    .expect("entry not under source path");
+                entry.path = if rest.as_os_str().is_empty() {
+                    destination.to_string()
+                } else {
+                    Path::new(destination)
+                        .join(rest)
+                        .to_str()
+                        .expect("invalid filename")
+                        .to_string()
+                };
+                entry.flags = (entry.flags & !MAX_PATH_SIZE)
+                    | cmp::min(entry.path.len() as u16, MAX_PATH_SIZE);
+
+                self.discard_conflicts(&entry);
+                self.store_entry(entry);
+            }
+        }
+        self.changed = true;
+    }
+
     pub fn store_entry(&mut self, entry: Entry) {
         self.entries.insert(entry.path.clone(), entry.clone());
 
@@ -373,8 +492,8 @@ impl Index {
             panic!("Signature: expected 'DIRC', but found {}", signature);
         }
 
-        if version != 2 {
-            panic!("Version: expected '2', but found {}", version);
+        if version != 2 && version != 3 {
+            panic!("Version: expected '2' or '3', but found {}", version);
         }
 
         count as usize
@@ -387,7 +506,9 @@ impl Index {
     ) -> Result<(), std::io::Error> {
         for _i in 0..count {
             let mut entry = checksum.read(MIN_ENTRY_SIZE)?;
-            while entry.last().unwrap() != &0u8 {
+            let flags = u16::from_be_bytes(entry[60..62].try_into().unwrap());
+            let path_offset = Entry::path_offset(flags);
+            while entry.len() <= path_offset || entry.last().unwrap() != &0u8 {
                 entry.extend_from_slice(&checksum.read(8)?);
             }
 
@@ -429,6 +550,20 @@ impl Index {
     pub fn entry_for_path(&self, path: &str) -> Option<&Entry> {
         self.entries.get(path)
     }
+
+    pub fn set_assume_unchanged(&mut self, pathname: &str, value: bool) {
+        if let Some(entry) = self.entries.get_mut(pathname) {
+            entry.set_assume_unchanged(value);
+            self.changed = true;
+        }
+    }
+
+    pub fn set_skip_worktree(&mut self, pathname: &str, value: bool) {
+        if let Some(entry) = self.entries.get_mut(pathname) {
+            entry.set_skip_worktree(value);
+            self.changed = true;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -579,4 +714,56 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn round_trip_extended_flags_written_by_stock_git() -> Result<(), std::io::Error> {
+        // Flag entries with stock git, which switches the index to
+        // version 3, then check that we read the flags and write the
+        // same bytes back
+
+        let mut temp_dir = generate_temp_name();
+        temp_dir.push_str("_jit_test");
+
+        let root_path = Path::new("/tmp").join(temp_dir);
+        let mut repo = Repository::new(&root_path);
+        fs::create_dir(&root_path)?;
+
+        fs::write(root_path.join("f1.txt"), b"file 1")?;
+        fs::write(root_path.join("f2.txt"), b"file 2")?;
+        fs::write(root_path.join("f3.txt"), b"file 3")?;
+
+        for args in &[
+            vec!["init", "."],
+            vec!["add", "."],
+            vec!["update-index", "--skip-worktree", "f1.txt"],
+            vec!["update-index", "--assume-unchanged", "f2.txt"],
+        ] {
+            Command::new("git")
+                .current_dir(&root_path)
+                .args(args)
+                .output()?;
+        }
+
+        let index_path = root_path.join(".git/index");
+        let git_index_contents = fs::read(&index_path)?;
+        assert_eq!(&3u32.to_be_bytes(), &git_index_contents[4..8]);
+
+        repo.index.load_for_update()?;
+        let flags: Vec<(bool, bool)> = repo
+            .index
+            .entries
+            .values()
+            .map(|entry| (entry.is_skip_worktree(), entry.is_assume_unchanged()))
+            .collect();
+        assert_eq!(vec![(true, false), (false, true), (false, false)], flags);
+
+        repo.index.set_assume_unchanged("f2.txt", true);
+        repo.index.write_updates()?;
+        assert_eq!(git_index_contents, fs::read(&index_path)?);
+
+        // Cleanup
+        fs::remove_dir_all(&root_path)?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,26 @@
+extern crate chrono;
+extern crate clap;
+extern crate crypto;
+extern crate flate2;
+extern crate rand;
+#[macro_use]
+extern crate lazy_static;
+extern crate regex;
+
+mod lockfile;
+
+pub mod config;
+pub mod database;
+pub mod diff;
+pub mod index;
+pub mod mailmap;
+pub mod pager;
+pub mod reflog;
+pub mod refs;
+pub mod repository;
+pub mod rev_list;
+pub mod revision;
+pub mod util;
+pub mod workspace;
+
+pub mod commands;
@@ -14,11 +14,19 @@ impl Lockfile {
     pub fn new(path: &Path) -> Lockfile {
         Lockfile {
             file_path: path.to_path_buf(),
-            lock_path: path.with_extension("lock"),
+            lock_path: Self::lock_path(path),
             lock: None,
         }
     }
 
+    // `<path>.lock`, keeping any extension `path` already has, so that
+    // eg. the tags `v1.0` and `v1.1` don't share a lock
+    fn lock_path(path: &Path) -> PathBuf {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        PathBuf::from(lock_path)
+    }
+
     pub fn hold_for_update(&mut self) -> Result<(), std::io::Error> {
         if self.lock.is_none() {
             let open_file = OpenOptions::new()
@@ -0,0 +1,191 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// One line of a `.mailmap` file. Commits whose email (and name, when
+/// given) match the commit identity are shown with the proper one.
+struct MailmapEntry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+/// Canonical author identities read from a `.mailmap` file, which
+/// holds lines of the forms:
+///
+/// ```text
+/// Proper Name <commit@email>
+/// <proper@email> <commit@email>
+/// Proper Name <proper@email> <commit@email>
+/// Proper Name <proper@email> Commit Name <commit@email>
+/// ```
+pub struct Mailmap {
+    entries: Vec<MailmapEntry>,
+}
+
+impl Mailmap {
+    /// Read the mailmap at `path`. A missing file gives an empty map.
+    pub fn load(path: &Path) -> Result<Mailmap, io::Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Mailmap::parse(&contents)),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Mailmap { entries: vec![] }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Mailmap {
+        let entries = contents.lines().filter_map(parse_line).collect();
+        Mailmap { entries }
+    }
+
+    /// Map a commit's name and email to the canonical identity.
+    /// Names and emails are compared case-insensitively, as git
+    /// does, though only for ASCII letters. An entry naming the
+    /// commit name wins over one matching the email alone, and later
+    /// lines win over earlier ones.
+    pub fn lookup(&self, name: &str, email: &str) -> (String, String) {
+        let matches_email = |entry: &&MailmapEntry| entry.commit_email.eq_ignore_ascii_case(email);
+
+        let entry = self
+            .entries
+            .iter()
+            .rev()
+            .filter(matches_email)
+            .find(|entry| match &entry.commit_name {
+                Some(commit_name) => commit_name.eq_ignore_ascii_case(name),
+                None => false,
+            })
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .rev()
+                    .filter(matches_email)
+                    .find(|entry| entry.commit_name.is_none())
+            });
+
+        match entry {
+            Some(entry) => (
+                entry
+                    .proper_name
+                    .clone()
+                    .unwrap_or_else(|| name.to_string()),
+                entry
+                    .proper_email
+                    .clone()
+                    .unwrap_or_else(|| email.to_string()),
+            ),
+            None => (name.to_string(), email.to_string()),
+        }
+    }
+}
+
+/// Split a line into its `Name <email>` pairs, ignoring comments.
+/// Returns `None` for blank, comment-only or malformed lines.
+fn parse_line(line: &str) -> Option<MailmapEntry> {
+    let mut idents = vec![];
+    let mut rest = line;
+
+    while let Some(open) = rest.find('<') {
+        let name = rest[..open].trim();
+        if name.starts_with('#') {
+            break;
+        }
+        let close = open + rest[open..].find('>')?;
+        let email = rest[open + 1..close].trim();
+
+        let name = if name.is_empty() {
+            None
+        } else {
+            Some(name.to_string())
+        };
+        idents.push((name, email.to_string()));
+        rest = &rest[close + 1..];
+    }
+
+    let mut idents = idents.into_iter();
+    match (idents.next(), idents.next()) {
+        (Some((Some(proper_name), commit_email)), None) => Some(MailmapEntry {
+            proper_name: Some(proper_name),
+            proper_email: None,
+            commit_name: None,
+            commit_email,
+        }),
+        (Some((proper_name, proper_email)), Some((commit_name, commit_email))) => {
+            Some(MailmapEntry {
+                proper_name,
+                proper_email: Some(proper_email),
+                commit_name,
+                commit_email,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_commit_identities_to_proper_ones() {
+        let mailmap = Mailmap::parse(
+            "# Comment line
+Joe Developer <joe@example.com>
+<jane@example.com> <jane@laptop.(none)>
+Other Author <other@author.xx> <nick2@company.xx> # trailing comment
+Santa Claus <santa.claus@northpole.xx> Old Santa <me@company.xx>
+Santa Impostor <me@company.xx>
+",
+        );
+
+        assert_eq!(
+            ("Joe Developer".to_string(), "JOE@example.com".to_string()),
+            mailmap.lookup("joe", "JOE@example.com")
+        );
+        assert_eq!(
+            ("Jane".to_string(), "jane@example.com".to_string()),
+            mailmap.lookup("Jane", "jane@laptop.(none)")
+        );
+        assert_eq!(
+            ("Other Author".to_string(), "other@author.xx".to_string()),
+            mailmap.lookup("nick2", "nick2@company.xx")
+        );
+        assert_eq!(
+            (
+                "Santa Claus".to_string(),
+                "santa.claus@northpole.xx".to_string()
+            ),
+            mailmap.lookup("Old Santa", "me@company.xx")
+        );
+        assert_eq!(
+            ("Santa Impostor".to_string(), "me@company.xx".to_string()),
+            mailmap.lookup("Someone Else", "me@company.xx")
+        );
+        assert_eq!(
+            ("Unmapped".to_string(), "un@mapped.xx".to_string()),
+            mailmap.lookup("Unmapped", "un@mapped.xx")
+        );
+    }
+
+    #[test]
+    fn ignores_case_of_commit_names_and_emails() {
+        let mailmap = Mailmap::parse(
+            "Santa Claus <santa.claus@northpole.xx> Old Santa <Me@Company.xx>
+Santa Impostor <me@company.xx>
+",
+        );
+
+        assert_eq!(
+            (
+                "Santa Claus".to_string(),
+                "santa.claus@northpole.xx".to_string()
+            ),
+            mailmap.lookup("old SANTA", "ME@company.XX")
+        );
+        assert_eq!(
+            ("Santa Impostor".to_string(), "ME@company.XX".to_string()),
+            mailmap.lookup("Young Santa", "ME@company.XX")
+        );
+    }
+}
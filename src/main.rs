@@ -1,30 +1,9 @@
-extern crate chrono;
-extern crate crypto;
-extern crate flate2;
-extern crate rand;
-#[macro_use]
-extern crate lazy_static;
-extern crate regex;
-extern crate clap;
-
 use std::collections::HashMap;
 use std::env;
-use std::io::{self, Write};
-
-mod lockfile;
-
-mod database;
-mod index;
-mod refs;
-mod repository;
-mod util;
-mod workspace;
-mod diff;
-mod pager;
-mod revision;
+use std::io;
+use std::process;
 
-mod commands;
-use commands::{execute, get_app, CommandContext};
+use rug::commands::{run, CommandContext};
 
 fn main() {
     let ctx = CommandContext {
@@ -34,15 +13,12 @@ fn main() {
         stdin: io::stdin(),
         stdout: io::stdout(),
         stderr: io::stderr(),
+        use_pager: true,
+        use_color: true,
     };
 
-    let matches = get_app().get_matches();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
 
-    match execute(matches, ctx) {
-        Ok(_) => (),
-        Err(msg) => {
-            io::stderr().write_all(msg.as_bytes()).unwrap();
-            std::process::exit(128);
-        }
-    }
+    process::exit(run(&args, ctx));
 }
@@ -65,20 +65,10 @@ mod utils {
 pub struct Pager;
 
 impl Pager {
-    pub fn setup_pager() {
-        let (git_pager, pager) = (std::env::var("GIT_PAGER"), std::env::var("PAGER"));
-
-        let cmd = match (git_pager, pager) {
-            (Ok(git_pager), _) => git_pager,
-            (_, Ok(pager)) => pager,
-            _ => PAGER_CMD.to_string(),
-        };
-
-        let pager_cmd = OsString::from(cmd);
-
-        for (k, v) in PAGER_ENV.iter() {
-            std::env::set_var(k, v);
-        }
+    /// Page the rest of stdout through `$GIT_PAGER` or `$PAGER` from
+    /// `env`, falling back to `less`
+    pub fn setup_pager(env: &HashMap<String, String>) {
+        let pager_cmd = OsString::from(Pager::command(env));
 
         let (pager_stdin, main_stdout) = utils::pipe();
         let pager_pid = utils::fork();
@@ -94,11 +84,42 @@ impl Pager {
                 utils::close(pager_stdin);
             }
             _ => {
-                // Parent-- executes pager
+                // Parent-- executes pager. This process becomes the
+                // pager, so its environment is the only one changed;
+                // as in git, settings the user already has are kept.
+                for (k, v) in PAGER_ENV.iter() {
+                    if !env.contains_key(*k) {
+                        std::env::set_var(k, v);
+                    }
+                }
                 utils::dup2(pager_stdin, libc::STDIN_FILENO);
                 utils::close(main_stdout);
                 utils::execvp(&pager_cmd);
             }
         }
     }
+
+    fn command(env: &HashMap<String, String>) -> String {
+        env.get("GIT_PAGER")
+            .or_else(|| env.get("PAGER"))
+            .cloned()
+            .unwrap_or_else(|| PAGER_CMD.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_git_pager_then_pager() {
+        let mut env = HashMap::new();
+        assert_eq!("less", Pager::command(&env));
+
+        env.insert("PAGER".to_string(), "more".to_string());
+        assert_eq!("more", Pager::command(&env));
+
+        env.insert("GIT_PAGER".to_string(), "cat".to_string());
+        assert_eq!("cat", Pager::command(&env));
+    }
 }
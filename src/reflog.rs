@@ -0,0 +1,201 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::lockfile::Lockfile;
+
+/// Object ID recorded when a ref is created or deleted
+pub const NULL_OID: &str = "0000000000000000000000000000000000000000";
+
+/// One line of a reflog, recording a ref moving from `old_oid` to
+/// `new_oid`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflogEntry {
+    pub old_oid: String,
+    pub new_oid: String,
+    // `Name <email>` of whoever moved the ref
+    pub identity: String,
+    pub time: i64,
+    pub timezone: String,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    fn parse(line: &str) -> Option<ReflogEntry> {
+        let mut parts = line.splitn(2, '\t');
+        let header = parts.next()?;
+        let message = parts.next().unwrap_or("").to_string();
+
+        let mut oids = header.splitn(3, ' ');
+        let old_oid = oids.next()?.to_string();
+        let new_oid = oids.next()?.to_string();
+
+        let mut rest = oids.next()?.rsplitn(3, ' ');
+        let timezone = rest.next()?.to_string();
+        let time = rest.next()?.parse().ok()?;
+        let identity = rest.next()?.to_string();
+
+        Some(ReflogEntry {
+            old_oid,
+            new_oid,
+            identity,
+            time,
+            timezone,
+            message,
+        })
+    }
+}
+
+/// The history of a single ref, as kept by git under `.git/logs`.
+/// Entries are in the order they were written, oldest first.
+///
+/// Every line is kept as it was read, so that writing the log back
+/// only drops the lines of removed entries. Messages are copied from
+/// commits and needn't be UTF-8, and lines that can't be parsed are
+/// left alone rather than lost.
+pub struct Reflog {
+    path: PathBuf,
+    // Each line with its newline, and its entry if it could be parsed
+    lines: Vec<(Vec<u8>, Option<ReflogEntry>)>,
+}
+
+impl Reflog {
+    /// Read the reflog at `path`. A missing file is an empty log.
+    pub fn load(path: &Path) -> Result<Reflog, io::Error> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        let lines = contents
+            .split_inclusive(|&byte| byte == b'\n')
+            .map(|line| {
+                let text = String::from_utf8_lossy(line);
+                let entry = ReflogEntry::parse(text.trim_end_matches('\n'));
+                (line.to_vec(), entry)
+            })
+            .collect();
+
+        Ok(Reflog {
+            path: path.to_path_buf(),
+            lines,
+        })
+    }
+
+    /// The entries that could be parsed, with any bytes of their
+    /// messages that aren't valid UTF-8 replaced
+    pub fn entries(&self) -> impl Iterator<Item = &ReflogEntry> {
+        self.lines.iter().filter_map(|(_, entry)| entry.as_ref())
+    }
+
+    /// Remove the entries `keep` returns false for, returning them in
+    /// order. Lines that couldn't be parsed are always kept.
+    pub fn retain<F>(&mut self, mut keep: F) -> Vec<ReflogEntry>
+    where
+        F: FnMut(&ReflogEntry) -> bool,
+    {
+        let mut removed = vec![];
+        let lines = std::mem::take(&mut self.lines);
+
+        for (line, entry) in lines {
+            match entry {
+                Some(entry) if !keep(&entry) => removed.push(entry),
+                entry => self.lines.push((line, entry)),
+            }
+        }
+
+        removed
+    }
+
+    pub fn write(&self) -> Result<(), io::Error> {
+        let mut lock = Lockfile::new(&self.path);
+        lock.hold_for_update()?;
+
+        for (line, _) in &self.lines {
+            lock.write_bytes(line)?;
+        }
+
+        lock.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::generate_temp_name;
+
+    #[test]
+    fn round_trips_entries() -> Result<(), io::Error> {
+        let contents = format!(
+            "{} 1111111111111111111111111111111111111111 A. U. Thor <author@example.com> 1500000000 +0100\tcommit (initial): first
+1111111111111111111111111111111111111111 2222222222222222222222222222222222222222 A. U. Thor <author@example.com> 1500000100 -0230\tcheckout: moving from master to topic
+",
+            NULL_OID
+        );
+
+        let mut temp_name = generate_temp_name();
+        temp_name.push_str("_rug_test_reflog");
+        let path = std::env::temp_dir().join(temp_name);
+        fs::write(&path, &contents)?;
+
+        let reflog = Reflog::load(&path)?;
+        let entries: Vec<_> = reflog.entries().collect();
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            ReflogEntry {
+                old_oid: "1111111111111111111111111111111111111111".to_string(),
+                new_oid: "2222222222222222222222222222222222222222".to_string(),
+                identity: "A. U. Thor <author@example.com>".to_string(),
+                time: 1_500_000_100,
+                timezone: "-0230".to_string(),
+                message: "checkout: moving from master to topic".to_string(),
+            },
+            *entries[1]
+        );
+
+        fs::write(&path, "")?;
+        reflog.write()?;
+        let written = fs::read_to_string(&path)?;
+        fs::remove_file(&path)?;
+        assert_eq!(contents, written);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_bytes_of_every_line() -> Result<(), io::Error> {
+        let mut contents = format!(
+            "{} 1111111111111111111111111111111111111111 A. U. Thor <author@example.com> 1500000000 +0100\tcommit (initial): caf",
+            NULL_OID
+        )
+        .into_bytes();
+        contents.extend_from_slice(b"\xe9\nnot a reflog line\n\n");
+        contents.extend_from_slice(
+            b"1111111111111111111111111111111111111111 2222222222222222222222222222222222222222 A. U. Thor <author@example.com> 1500000100 +0100\tcommit: second",
+        );
+
+        let mut temp_name = generate_temp_name();
+        temp_name.push_str("_rug_test_reflog");
+        let path = std::env::temp_dir().join(temp_name);
+        fs::write(&path, &contents)?;
+
+        let mut reflog = Reflog::load(&path)?;
+        assert!(reflog.retain(|_| true).is_empty());
+        assert_eq!(
+            vec!["commit (initial): caf\u{fffd}", "commit: second"],
+            reflog
+                .entries()
+                .map(|entry| entry.message.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        fs::write(&path, "")?;
+        reflog.write()?;
+        let written = fs::read(&path)?;
+        fs::remove_file(&path)?;
+        assert_eq!(contents, written);
+
+        Ok(())
+    }
+}
@@ -1,10 +1,24 @@
+use crate::database::{Database, ParsedObject};
 use crate::lockfile::Lockfile;
 use crate::util;
 use regex::{Regex, RegexSet};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fs::{self, DirEntry, File};
 use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::cmp::{Ord, Ordering};
+use std::time::SystemTime;
+
+const PACKED_REFS_HEADER: &str = "# pack-refs with: sorted \n";
+// Every ref that points at an annotated tag has a `^` line
+const PEELED_PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+/// Directories searched, in order, when resolving a short ref name
+/// like `master` or `v1.0`
+const REF_PREFIXES: [&str; 5] = ["", "refs/", "refs/tags/", "refs/heads/", "refs/remotes/"];
 
 lazy_static! {
     static ref INVALID_FILENAME: RegexSet = {
@@ -48,14 +62,32 @@ impl Ord for Ref {
     }
 }
 
+/// An entry in `.git/packed-refs`. `peeled` holds the object an
+/// annotated tag ultimately points to, when the file records it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub oid: String,
+    pub peeled: Option<String>,
+}
+
+/// Identifies one version of `packed-refs` on disk; the file is
+/// replaced by renaming, so a new version has a new inode
+type PackedRefsStat = (SystemTime, u64, u64);
+
+// The stat `packed-refs` had when it was parsed, or `None` if it
+// didn't exist
+type PackedRefsCache = Option<(Option<PackedRefsStat>, Rc<BTreeMap<String, PackedRef>>)>;
+
 pub struct Refs {
     pathname: PathBuf,
+    packed_refs: RefCell<PackedRefsCache>,
 }
 
 impl Refs {
     pub fn new(pathname: &Path) -> Refs {
         Refs {
             pathname: pathname.to_path_buf(),
+            packed_refs: RefCell::new(None),
         }
     }
 
@@ -71,6 +103,48 @@ impl Refs {
         (*self.pathname).join("refs/heads")
     }
 
+    fn packed_refs_path(&self) -> PathBuf {
+        (*self.pathname).join("packed-refs")
+    }
+
+    fn logs_path(&self) -> PathBuf {
+        (*self.pathname).join("logs")
+    }
+
+    pub fn reflog_path(&self, name: &str) -> PathBuf {
+        self.logs_path().join(name)
+    }
+
+    /// Full name of the ref whose reflog an abbreviated `name` such
+    /// as `master` refers to, if that reflog exists
+    pub fn reflog_name(&self, name: &str) -> Option<String> {
+        REF_PREFIXES
+            .iter()
+            .map(|prefix| format!("{}{}", prefix, name))
+            .find(|full_name| self.reflog_path(full_name).is_file())
+    }
+
+    /// Names of all refs that have a reflog, eg. `HEAD` and
+    /// `refs/heads/master`
+    pub fn list_reflogs(&self) -> Vec<String> {
+        let mut names = vec![];
+        let mut dirs = vec![self.logs_path()];
+
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if !path.to_string_lossy().ends_with(".lock") {
+                    names.push(util::relative_path_from(&path, &self.logs_path()));
+                }
+            }
+        }
+
+        names.sort();
+        names
+    }
+
     pub fn update_ref_file(&self, path: &Path, oid: &str) -> Result<(), std::io::Error> {
         let mut lock = Lockfile::new(path);
         lock.hold_for_update()?;
@@ -84,7 +158,7 @@ impl Refs {
     pub fn set_head(&self, revision: &str, oid: &str) -> Result<(), std::io::Error> {
         let path = self.heads_path().join(revision);
 
-        if path.exists() {
+        if self.branch_exists(revision)? {
             let relative = util::relative_path_from(Path::new(&path), &self.pathname);
             self.update_ref_file(&self.head_path(), &format!("ref: {}", relative))
         } else {
@@ -96,21 +170,243 @@ impl Refs {
         self.read_symref(&self.head_path())
     }
 
-    fn path_for_name(&self, name: &str) -> Option<PathBuf> {
-        let prefixes = [self.pathname.clone(), self.refs_path(), self.heads_path()];
-        for prefix in &prefixes {
-            if prefix.join(name).exists() {
-                return Some(prefix.join(name));
+    /// Resolve a (possibly abbreviated) ref name. At each prefix a
+    /// loose ref file takes precedence over an entry in
+    /// `packed-refs`.
+    pub fn read_ref(&self, name: &str) -> Option<String> {
+        let packed = self.packed_refs().unwrap_or_default();
+
+        for prefix in REF_PREFIXES.iter() {
+            let full_name = format!("{}{}", prefix, name);
+            let path = self.pathname.join(&full_name);
+
+            if path.is_file() {
+                return self.read_symref(&path);
+            }
+            if let Some(packed_ref) = packed.get(&full_name) {
+                return Some(packed_ref.oid.clone());
             }
         }
         None
     }
 
-    pub fn read_ref(&self, name: &str) -> Option<String> {
-        if let Some(path) = self.path_for_name(name) {
-            self.read_symref(&path)
+    /// Parse `.git/packed-refs` into a map keyed by full ref name,
+    /// eg. `refs/heads/master`. A `^<oid>` line records the peeled
+    /// value of the annotated tag on the line before it. A file that
+    /// can't be read, or isn't UTF-8, is an error.
+    pub fn read_packed_refs(&self) -> Result<BTreeMap<String, PackedRef>, io::Error> {
+        Ok((*self.packed_refs()?).clone())
+    }
+
+    /// The parsed `packed-refs`, only read again once the file has
+    /// changed on disk
+    fn packed_refs(&self) -> Result<Rc<BTreeMap<String, PackedRef>>, io::Error> {
+        let stat = fs::metadata(self.packed_refs_path())
+            .ok()
+            .and_then(|metadata| Some((metadata.modified().ok()?, metadata.len(), metadata.ino())));
+
+        if let Some((cached_stat, refs)) = &*self.packed_refs.borrow() {
+            if *cached_stat == stat {
+                return Ok(refs.clone());
+            }
+        }
+
+        let refs = Rc::new(self.parse_packed_refs()?);
+        *self.packed_refs.borrow_mut() = Some((stat, refs.clone()));
+        Ok(refs)
+    }
+
+    fn parse_packed_refs(&self) -> Result<BTreeMap<String, PackedRef>, io::Error> {
+        let mut refs: BTreeMap<String, PackedRef> = BTreeMap::new();
+        let contents = match fs::read(self.packed_refs_path()) {
+            Ok(contents) => String::from_utf8(contents).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "packed-refs is not valid UTF-8")
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(refs),
+            Err(e) => return Err(e),
+        };
+
+        let mut last_name: Option<String> = None;
+        for line in contents.lines() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(peeled) = line.strip_prefix('^') {
+                if let Some(packed_ref) = last_name.as_ref().and_then(|name| refs.get_mut(name)) {
+                    packed_ref.peeled = Some(peeled.to_string());
+                }
+                continue;
+            }
+
+            let parts: Vec<&str> = line.splitn(2, ' ').collect();
+            if let [oid, name] = parts.as_slice() {
+                refs.insert(
+                    name.to_string(),
+                    PackedRef {
+                        oid: oid.to_string(),
+                        peeled: None,
+                    },
+                );
+                last_name = Some(name.to_string());
+            } else {
+                last_name = None;
+            }
+        }
+
+        Ok(refs)
+    }
+
+    /// Look up a single packed ref. An unreadable `packed-refs` holds
+    /// no refs as far as lookups go, while listing or rewriting it
+    /// fails.
+    fn read_packed_ref(&self, path: &Path) -> Option<String> {
+        let name = path.strip_prefix(&self.pathname).ok()?.to_str()?;
+        self.packed_refs()
+            .ok()?
+            .get(name)
+            .map(|packed_ref| packed_ref.oid.clone())
+    }
+
+    fn write_packed_refs(
+        &self,
+        mut lock: Lockfile,
+        header: &str,
+        refs: &BTreeMap<String, PackedRef>,
+    ) -> Result<(), io::Error> {
+        *self.packed_refs.borrow_mut() = None;
+
+        lock.write(header)?;
+        for (name, packed_ref) in refs {
+            lock.write(&format!("{} {}\n", packed_ref.oid, name))?;
+            if let Some(peeled) = &packed_ref.peeled {
+                lock.write(&format!("^{}\n", peeled))?;
+            }
+        }
+        lock.commit()
+    }
+
+    fn delete_packed_ref(&self, name: &str) -> Result<(), io::Error> {
+        let mut lock = Lockfile::new(&self.packed_refs_path());
+        lock.hold_for_update()?;
+
+        let mut refs = self.read_packed_refs()?;
+        if refs.remove(name).is_none() {
+            return lock.rollback();
+        }
+
+        // Removing a ref leaves whatever the header claims true
+        let header = match fs::read_to_string(self.packed_refs_path()) {
+            Ok(contents) if contents.starts_with("# pack-refs with:") => {
+                contents.lines().next().unwrap_or("").to_string() + "\n"
+            }
+            _ => PACKED_REFS_HEADER.to_string(),
+        };
+        self.write_packed_refs(lock, &header, &refs)
+    }
+
+    /// Move loose refs into `packed-refs` and delete the loose
+    /// files. Without `all`, only tags are packed. Symbolic refs are
+    /// always left alone, as are refs another process has locked.
+    /// Refs to annotated tags are recorded along with what they peel
+    /// to.
+    pub fn pack_refs(&self, database: &mut Database, all: bool) -> Result<(), io::Error> {
+        let mut lock = Lockfile::new(&self.packed_refs_path());
+        lock.hold_for_update()?;
+
+        let mut refs = self.read_packed_refs()?;
+        let mut loose = vec![];
+
+        for r#ref in self.list_refs(&self.refs_path()) {
+            let name = match r#ref {
+                Ref::SymRef { path } => path,
+                Ref::Ref { .. } => continue,
+            };
+            if name.ends_with(".lock") || !(all || name.starts_with("refs/tags/")) {
+                continue;
+            }
+
+            // Held until the loose file is deleted, so the ref can't
+            // move after its value is read
+            let path = self.pathname.join(&name);
+            let mut ref_lock = Lockfile::new(&path);
+            if ref_lock.hold_for_update().is_err() {
+                continue;
+            }
+
+            match Self::read_oid_or_symref(&path) {
+                Some(Ref::Ref { oid }) => {
+                    // A peeled value is only still valid if the ref
+                    // hasn't moved since it was last packed
+                    let peeled = refs
+                        .get(&name)
+                        .filter(|packed_ref| packed_ref.oid == oid)
+                        .and_then(|packed_ref| packed_ref.peeled.clone());
+                    refs.insert(name, PackedRef { oid, peeled });
+                    loose.push((path, ref_lock));
+                }
+                _ => ref_lock.rollback()?,
+            }
+        }
+
+        let mut fully_peeled = true;
+        for packed_ref in refs.values_mut() {
+            match Self::peel(database, &packed_ref.oid) {
+                Some(peeled) => packed_ref.peeled = peeled,
+                // Objects only found in a pack can't be read, so keep
+                // any value recorded earlier
+                None => fully_peeled &= packed_ref.peeled.is_some(),
+            }
+        }
+
+        let header = if fully_peeled {
+            PEELED_PACKED_REFS_HEADER
         } else {
-            None
+            PACKED_REFS_HEADER
+        };
+        if let Err(e) = self.write_packed_refs(lock, header, &refs) {
+            for (_, mut ref_lock) in loose {
+                ref_lock.rollback()?;
+            }
+            return Err(e);
+        }
+
+        for (path, mut ref_lock) in loose {
+            fs::remove_file(&path)?;
+            ref_lock.rollback()?;
+            self.remove_empty_parent_dirs(&path);
+        }
+
+        Ok(())
+    }
+
+    /// Follow `oid` through annotated tags to the object they end at,
+    /// which is `None` if `oid` isn't a tag. Returns `None` when an
+    /// object on the way can't be read.
+    fn peel(database: &mut Database, oid: &str) -> Option<Option<String>> {
+        let mut target = oid.to_string();
+        while let ParsedObject::Tag(tag) = database.try_load(&target).ok()? {
+            target = tag.object.clone();
+        }
+
+        if target == oid {
+            Some(None)
+        } else {
+            Some(Some(target))
+        }
+    }
+
+    /// Remove directories left empty by deleting a ref, stopping
+    /// short of the top-level `refs/heads`, `refs/tags`, etc.
+    fn remove_empty_parent_dirs(&self, path: &Path) {
+        let refs_path = self.refs_path();
+        for dir in path.ancestors().skip(1) {
+            if dir.parent().map(|parent| parent == refs_path).unwrap_or(true) {
+                break;
+            }
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
         }
     }
 
@@ -148,7 +444,7 @@ impl Refs {
         match r#ref {
             Some(Ref::SymRef { path }) => self.read_symref(&self.pathname.join(&path)),
             Some(Ref::Ref { oid }) => Some(oid),
-            None => None,
+            None => self.read_packed_ref(path),
         }
     }
 
@@ -187,7 +483,7 @@ impl Refs {
             return Err(format!("{} is not a valid branch name.\n", branch_name));
         }
 
-        if path.as_path().exists() {
+        if self.branch_exists(branch_name).map_err(|e| e.to_string())? {
             return Err(format!("A branch named {} already exists.\n", branch_name));
         }
 
@@ -196,8 +492,45 @@ impl Refs {
             .map_err(|e| e.to_string())
     }
 
-    pub fn list_branches(&self) -> Vec<Ref> {
-        self.list_refs(&self.heads_path())
+    fn branch_exists(&self, branch_name: &str) -> Result<bool, io::Error> {
+        Ok(self.heads_path().join(branch_name).is_file()
+            || self
+                .packed_refs()?
+                .contains_key(&format!("refs/heads/{}", branch_name)))
+    }
+
+    pub fn list_branches(&self) -> Result<Vec<Ref>, io::Error> {
+        let mut branches = self.list_refs(&self.heads_path());
+
+        for name in self.packed_refs()?.keys() {
+            if !name.starts_with("refs/heads/") {
+                continue;
+            }
+            let r#ref = Ref::SymRef { path: name.clone() };
+            if !branches.contains(&r#ref) {
+                branches.push(r#ref);
+            }
+        }
+
+        branches.sort();
+        Ok(branches)
+    }
+
+    /// Every ref under `refs/`, loose or packed, sorted by name
+    pub fn list_all_refs(&self) -> Result<Vec<Ref>, io::Error> {
+        let mut names: Vec<_> = self
+            .list_refs(&self.refs_path())
+            .into_iter()
+            .filter_map(|r#ref| match r#ref {
+                Ref::SymRef { path } if !path.ends_with(".lock") => Some(path),
+                _ => None,
+            })
+            .collect();
+        names.extend(self.packed_refs()?.keys().cloned());
+        names.sort();
+        names.dedup();
+
+        Ok(names.into_iter().map(|path| Ref::SymRef { path }).collect())
     }
 
     fn name_to_symref(&self, name: DirEntry) -> Vec<Ref> {
@@ -240,16 +573,26 @@ impl Refs {
     pub fn delete_branch(&self, branch_name: &str) -> Result<String, String> {
         let path = self.heads_path().join(branch_name);
 
+        // A branch that is only in `packed-refs` may have no directory
+        // to hold its lock file
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
         let mut lockfile = Lockfile::new(&path);
         lockfile.hold_for_update().map_err(|e| e.to_string())?;
 
-        if let Some(oid) = self.read_symref(&path) {
-            fs::remove_file(path).map_err(|e| e.to_string())?;
-            // To remove the .lock file
-            lockfile.rollback().map_err(|e| e.to_string())?;
-            Ok(oid)
-        } else {
-            return Err(format!("branch {} not found", branch_name));
+        let oid = self.read_symref(&path);
+        if oid.is_some() {
+            if path.exists() {
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
+            }
+            self.delete_packed_ref(&format!("refs/heads/{}", branch_name))
+                .map_err(|e| e.to_string())?;
         }
+        // To remove the .lock file
+        lockfile.rollback().map_err(|e| e.to_string())?;
+        self.remove_empty_parent_dirs(&path);
+
+        oid.ok_or_else(|| format!("branch {} not found", branch_name))
     }
 }
@@ -1,7 +1,7 @@
 use crate::database::tree::TreeEntry;
 use crate::index::Entry;
 use crate::repository::{ChangeType, Repository};
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -47,10 +47,11 @@ pub struct Migration<'a> {
     pub mkdirs: BTreeSet<PathBuf>,
     pub rmdirs: BTreeSet<PathBuf>,
     pub errors: Vec<String>,
-    pub conflicts: HashMap<ConflictType, HashSet<PathBuf>>,
+    pub conflicts: BTreeMap<ConflictType, BTreeSet<PathBuf>>,
+    force: bool,
 }
 
-#[derive(Hash, PartialEq, Eq)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ConflictType {
     StaleFile,
     StaleDirectory,
@@ -66,9 +67,12 @@ pub enum Action {
 }
 
 impl<'a> Migration<'a> {
+    /// With `force`, local changes are overwritten instead of being
+    /// reported as conflicts.
     pub fn new(
         repo: &'a mut Repository,
         tree_diff: HashMap<PathBuf, (Option<TreeEntry>, Option<TreeEntry>)>,
+        force: bool,
     ) -> Migration<'a> {
         // TODO: can be a struct instead(?)
         let mut changes = HashMap::new();
@@ -77,11 +81,11 @@ impl<'a> Migration<'a> {
         changes.insert(Action::Update, vec![]);
 
         let conflicts = {
-            let mut m = HashMap::new();
-            m.insert(ConflictType::StaleFile, HashSet::new());
-            m.insert(ConflictType::StaleDirectory, HashSet::new());
-            m.insert(ConflictType::UntrackedOverwritten, HashSet::new());
-            m.insert(ConflictType::UntrackedRemoved, HashSet::new());
+            let mut m = BTreeMap::new();
+            m.insert(ConflictType::StaleFile, BTreeSet::new());
+            m.insert(ConflictType::StaleDirectory, BTreeSet::new());
+            m.insert(ConflictType::UntrackedOverwritten, BTreeSet::new());
+            m.insert(ConflictType::UntrackedRemoved, BTreeSet::new());
             m
         };
 
@@ -93,6 +97,7 @@ impl<'a> Migration<'a> {
             rmdirs: BTreeSet::new(),
             errors: vec![],
             conflicts,
+            force,
         }
     }
     pub fn apply_changes(&mut self) -> Result<(), String> {
@@ -108,7 +113,9 @@ impl<'a> Migration<'a> {
 
     fn plan_changes(&mut self) -> Result<(), Vec<String>> {
         for (path, (old_item, new_item)) in self.diff.clone() {
-            self.check_for_conflict(&path, &old_item, &new_item);
+            if !self.force {
+                self.check_for_conflict(&path, &old_item, &new_item);
+            }
             self.record_change(&path, old_item, new_item);
         }
 
@@ -1,3 +1,4 @@
+use crate::config::Config;
 use crate::database::blob::Blob;
 use crate::database::commit::Commit;
 use crate::database::object::Object;
@@ -31,6 +32,7 @@ enum ChangeKind {
 }
 
 pub struct Repository {
+    pub config: Config,
     pub database: Database,
     pub index: Index,
     pub refs: Refs,
@@ -48,14 +50,30 @@ pub struct Repository {
 
 impl Repository {
     pub fn new(root_path: &Path) -> Repository {
-        let git_path = root_path.join(".git");
+        Repository::with_git_dir(root_path, &root_path.join(".git"))
+    }
+
+    /// A repository whose git directory is `git_path` rather than
+    /// `.git` in the working tree, as with `GIT_DIR`
+    pub fn with_git_dir(root_path: &Path, git_path: &Path) -> Repository {
         let db_path = git_path.join("objects");
 
+        let mut config = Config::new(&git_path.join("config"));
+        let mut database = Database::new(&db_path);
+        // Commands that rely on the config report errors reading it
+        // themselves, and `check_config` reports an invalid limit
+        if config.load().is_ok() {
+            if let Ok(Some(limit)) = config.get_size(&["core", "objectCacheLimit"]) {
+                database.set_cache_limit(limit);
+            }
+        }
+
         Repository {
-            database: Database::new(&db_path),
+            config,
+            database,
             index: Index::new(&git_path.join("index")),
-            refs: Refs::new(&git_path),
-            workspace: Workspace::new(git_path.parent().unwrap()),
+            refs: Refs::new(git_path),
+            workspace: Workspace::new(root_path),
 
             root_path: root_path.to_path_buf(),
             stats: HashMap::new(),
@@ -67,6 +85,20 @@ impl Repository {
         }
     }
 
+    /// Check the settings `new` reads from the git directory
+    /// `git_path`, since it can't report them being invalid itself
+    pub fn check_config(git_path: &Path) -> Result<(), String> {
+        let mut config = Config::new(&git_path.join("config"));
+        if config.load().is_err() {
+            return Ok(());
+        }
+
+        config
+            .get_size(&["core", "objectCacheLimit"])
+            .map(|_| ())
+            .map_err(|e| format!("fatal: {}\n", e))
+    }
+
     pub fn initialize_status(&mut self) -> Result<(), String> {
         self.scan_workspace(&self.root_path.clone()).unwrap();
         self.load_head_tree();
@@ -237,6 +269,10 @@ impl Repository {
 
     /// Adds modified entries to self.changed
     fn check_index_against_workspace(&mut self, mut entry: &mut index::Entry) {
+        if entry.skips_workspace_checks() {
+            return;
+        }
+
         let stat = self.stats.get(&entry.path);
         let status = self.compare_index_to_workspace(Some(entry), stat);
         if status == ChangeType::NoChange {
@@ -283,7 +319,8 @@ impl Repository {
     pub fn migration(
         &mut self,
         tree_diff: HashMap<PathBuf, (Option<TreeEntry>, Option<TreeEntry>)>,
+        force: bool,
     ) -> Migration {
-        Migration::new(self, tree_diff)
+        Migration::new(self, tree_diff, force)
     }
 }
@@ -0,0 +1,112 @@
+use crate::database::commit::Commit;
+use crate::database::ParsedObject;
+use crate::repository::Repository;
+use crate::revision::Revision;
+use std::collections::{HashSet, VecDeque};
+
+/// Walks the history reachable from a set of start revisions,
+/// yielding each commit once, newest first. A commit that can't be
+/// read, eg. because it is only in a pack, ends the walk with an
+/// error.
+pub struct RevList<'a> {
+    repo: &'a mut Repository,
+    queue: VecDeque<Commit>,
+    seen: HashSet<String>,
+    error: Option<String>,
+}
+
+impl<'a> RevList<'a> {
+    /// Start from `revs`, or from HEAD when none are given. An unborn
+    /// HEAD yields no commits.
+    pub fn new(repo: &'a mut Repository, revs: &[&str]) -> Result<RevList<'a>, String> {
+        let mut start_oids = vec![];
+        if revs.is_empty() {
+            start_oids.extend(repo.refs.read_head());
+        }
+
+        for rev in revs {
+            let mut revision =
+                Revision::new(repo, rev).map_err(|_| format!("fatal: bad revision '{}'\n", rev))?;
+
+            match revision.resolve() {
+                Ok(oid) => start_oids.push(oid),
+                Err(errors) => {
+                    let mut v = vec![];
+                    for error in errors {
+                        v.push(format!("error: {}", error.message));
+                        for h in error.hint {
+                            v.push(format!("hint: {}", h));
+                        }
+                    }
+                    v.push("\n".to_string());
+
+                    return Err(v.join("\n"));
+                }
+            }
+        }
+
+        RevList::from_oids(repo, &start_oids)
+    }
+
+    /// Start from already resolved object IDs. Any that aren't
+    /// commits are skipped.
+    pub fn from_oids(repo: &'a mut Repository, oids: &[String]) -> Result<RevList<'a>, String> {
+        let mut rev_list = RevList {
+            repo,
+            queue: VecDeque::new(),
+            seen: HashSet::new(),
+            error: None,
+        };
+        for oid in oids {
+            rev_list.enqueue(oid)?;
+        }
+
+        Ok(rev_list)
+    }
+
+    /// Insert a commit into the queue, keeping it ordered by
+    /// commit date. Annotated tags are followed to their target.
+    fn enqueue(&mut self, oid: &str) -> Result<(), String> {
+        if !self.seen.insert(oid.to_string()) {
+            return Ok(());
+        }
+
+        let commit = match self.repo.database.try_load(oid)? {
+            ParsedObject::Commit(commit) => commit.clone(),
+            ParsedObject::Tag(tag) => {
+                let target = tag.object.clone();
+                return self.enqueue(&target);
+            }
+            _ => return Ok(()),
+        };
+
+        let index = self
+            .queue
+            .iter()
+            .position(|c| c.committer.time < commit.committer.time)
+            .unwrap_or(self.queue.len());
+        self.queue.insert(index, commit);
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for RevList<'a> {
+    type Item = Result<Commit, String>;
+
+    fn next(&mut self) -> Option<Result<Commit, String>> {
+        if let Some(error) = self.error.take() {
+            self.queue.clear();
+            return Some(Err(error));
+        }
+
+        let commit = self.queue.pop_front()?;
+        for parent in &commit.parents {
+            if let Err(error) = self.enqueue(parent) {
+                self.error = Some(error);
+                break;
+            }
+        }
+
+        Some(Ok(commit))
+    }
+}
@@ -1,8 +1,9 @@
 use crate::database::{commit, Database, ParsedObject};
 use crate::repository::Repository;
 use regex::{Regex, RegexSet};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::path::Path;
 
 lazy_static! {
     static ref INVALID_NAME: RegexSet = {
@@ -17,8 +18,12 @@ lazy_static! {
         ])
         .unwrap()
     };
-    static ref PARENT: Regex = { Regex::new(r"^(.+)\^$").unwrap() };
-    static ref ANCESTOR: Regex = { Regex::new(r"^(.+)~(\d+)$").unwrap() };
+    static ref MESSAGE_SEARCH: Regex = Regex::new(r"^:/(.+)$").unwrap();
+    static ref TREE_PATH: Regex = Regex::new(r"^([^:]+):(.*)$").unwrap();
+    static ref PARENT: Regex = Regex::new(r"^(.+)\^(\d*)$").unwrap();
+    static ref ANCESTOR: Regex = Regex::new(r"^(.+)~(\d*)$").unwrap();
+    static ref UPSTREAM: Regex = Regex::new(r"^(.*)@\{(?i:u|upstream)\}$").unwrap();
+    static ref OID_PREFIX: Regex = Regex::new(r"^[0-9a-f]{4,40}$").unwrap();
     static ref REF_ALIASES: HashMap<&'static str, &'static str> = {
         let mut m = HashMap::new();
         m.insert("@", "HEAD");
@@ -46,8 +51,42 @@ impl fmt::Display for HintedError {
 #[derive(Debug, Clone)]
 pub enum Rev {
     Ref { name: String },
-    Parent { rev: Box<Rev> },
+    /// `<rev>^<n>`: the nth parent, or the commit itself for `^0`
+    Parent { rev: Box<Rev>, n: i32 },
+    /// `<rev>~<n>`: the nth generation ancestor, following first
+    /// parents
     Ancestor { rev: Box<Rev>, n: i32 },
+    /// `<branch>@{upstream}`, with no branch meaning the current one
+    Upstream { branch: Option<String> },
+    /// `:/<pattern>`: the youngest reachable commit whose message
+    /// matches
+    MessageSearch { pattern: String },
+    /// `<rev>:<path>`: a blob or tree inside the revision's tree
+    TreePath { rev: Box<Rev>, path: String },
+}
+
+/// The object a revision resolved to, tagged with its type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevObject {
+    Commit(String),
+    Tree(String),
+    Blob(String),
+}
+
+impl RevObject {
+    pub fn oid(&self) -> &str {
+        match self {
+            RevObject::Commit(oid) | RevObject::Tree(oid) | RevObject::Blob(oid) => oid,
+        }
+    }
+
+    fn obj_type(&self) -> &str {
+        match self {
+            RevObject::Commit(_) => "commit",
+            RevObject::Tree(_) => "tree",
+            RevObject::Blob(_) => "blob",
+        }
+    }
 }
 
 pub struct Revision<'a> {
@@ -58,25 +97,49 @@ pub struct Revision<'a> {
 }
 
 impl<'a> Revision<'a> {
-    pub fn new(repo: &'a mut Repository, expr: &str) -> Revision<'a> {
-        Revision {
+    pub fn new(repo: &'a mut Repository, expr: &str) -> Result<Revision<'a>, String> {
+        let query =
+            Self::parse(expr).ok_or_else(|| format!("fatal: invalid object name '{}'\n", expr))?;
+
+        Ok(Revision {
             repo,
             expr: expr.to_string(),
-            query: Self::parse(expr).expect("Revision parse failed"),
+            query,
             errors: vec![],
-        }
+        })
     }
 
     pub fn parse(revision: &str) -> Option<Rev> {
-        if let Some(caps) = PARENT.captures(revision) {
-            let rev = Revision::parse(&caps[1]).expect("parsing parent rev failed");
-            return Some(Rev::Parent { rev: Box::new(rev) });
+        if let Some(caps) = MESSAGE_SEARCH.captures(revision) {
+            Some(Rev::MessageSearch {
+                pattern: caps[1].to_string(),
+            })
+        } else if let Some(caps) = TREE_PATH.captures(revision) {
+            let rev = Revision::parse(&caps[1])?;
+            Some(Rev::TreePath {
+                rev: Box::new(rev),
+                path: caps[2].to_string(),
+            })
+        } else if let Some(caps) = PARENT.captures(revision) {
+            let rev = Revision::parse(&caps[1])?;
+            Some(Rev::Parent {
+                rev: Box::new(rev),
+                n: Self::parse_count(&caps[2])?,
+            })
         } else if let Some(caps) = ANCESTOR.captures(revision) {
-            let rev = Revision::parse(&caps[1]).expect("parsing ancestor rev failed");
-            return Some(Rev::Ancestor {
+            let rev = Revision::parse(&caps[1])?;
+            Some(Rev::Ancestor {
                 rev: Box::new(rev),
-                n: i32::from_str_radix(&caps[2], 10).expect("could not parse ancestor number"),
-            });
+                n: Self::parse_count(&caps[2])?,
+            })
+        } else if let Some(caps) = UPSTREAM.captures(revision) {
+            let branch = REF_ALIASES
+                .get(&caps[1])
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| caps[1].to_string());
+            Some(Rev::Upstream {
+                branch: Some(branch).filter(|b| !b.is_empty() && b != "HEAD"),
+            })
         } else if Revision::is_valid_ref(revision) {
             let rev = REF_ALIASES.get(revision).unwrap_or(&revision);
             Some(Rev::Ref {
@@ -87,42 +150,96 @@ impl<'a> Revision<'a> {
         }
     }
 
+    /// Counts after `^` and `~` default to 1 when omitted
+    fn parse_count(digits: &str) -> Option<i32> {
+        if digits.is_empty() {
+            Some(1)
+        } else {
+            digits.parse().ok()
+        }
+    }
+
     fn is_valid_ref(revision: &str) -> bool {
         INVALID_NAME.matches(revision).into_iter().count() == 0
     }
 
+    /// Resolve to a commit ID, failing if the revision names some
+    /// other type of object.
     pub fn resolve(&mut self) -> Result<String, Vec<HintedError>> {
+        match self.resolve_object()? {
+            RevObject::Commit(oid) => Ok(oid),
+            object => {
+                let message = format!(
+                    "object {} is a {}, not a commit",
+                    object.oid(),
+                    object.obj_type()
+                );
+                self.errors.push(HintedError {
+                    message,
+                    hint: vec![],
+                });
+                Err(self.errors.clone())
+            }
+        }
+    }
+
+    /// Resolve to any object, reporting which type it is.
+    pub fn resolve_object(&mut self) -> Result<RevObject, Vec<HintedError>> {
         match self.resolve_query(self.query.clone()) {
-            Some(revision) => {
-                if self.load_commit(&revision).is_some() {
-                    Ok(revision)
-                } else {
-                    Err(self.errors.clone())
+            Some(mut oid) => loop {
+                // Tags are peeled to the object they point at
+                match self.load_object(&oid) {
+                    Some(ParsedObject::Commit(_)) => return Ok(RevObject::Commit(oid)),
+                    Some(ParsedObject::Tree(_)) => return Ok(RevObject::Tree(oid)),
+                    Some(ParsedObject::Blob(_)) => return Ok(RevObject::Blob(oid)),
+                    Some(ParsedObject::Tag(tag)) => oid = tag.object.clone(),
+                    None => return Err(self.errors.clone()),
                 }
+            },
+            None => {
+                if self.errors.is_empty() {
+                    self.errors.push(HintedError {
+                        message: format!("Not a valid object name: '{}'.", self.expr),
+                        hint: vec![],
+                    });
+                }
+                Err(self.errors.clone())
             }
-            None => Err(self.errors.clone()),
         }
     }
 
-    /// Resolve Revision to commit object ID.
+    /// Resolve Revision to an object ID.
     pub fn resolve_query(&mut self, query: Rev) -> Option<String> {
         match query {
-            Rev::Ref { name } => self.read_ref(&name),
-            Rev::Parent { rev } => {
-                let oid = self.resolve_query(*rev).expect("Invalid parent rev");
-                self.commit_parent(&oid)
+            Rev::Ref { name } => {
+                let oid = self.read_ref(&name)?;
+                self.peel(&oid)
+            }
+            Rev::Parent { rev, n } => {
+                let oid = self.resolve_query(*rev)?;
+                if n == 0 {
+                    self.load_commit(&oid)?;
+                    Some(oid)
+                } else {
+                    self.commit_parents(&oid).get(n as usize - 1).cloned()
+                }
             }
             Rev::Ancestor { rev, n } => {
-                let mut oid = self.resolve_query(*rev).expect("Invalid ancestor rev");
+                let mut oid = self.resolve_query(*rev)?;
                 for _ in 0..n {
-                    if let Some(parent_oid) = self.commit_parent(&oid) {
-                        oid = parent_oid
-                    } else {
-                        break;
-                    }
+                    oid = self.commit_parents(&oid).first()?.clone();
                 }
                 Some(oid)
             }
+            Rev::Upstream { branch } => {
+                let oid = self.upstream(branch)?;
+                self.peel(&oid)
+            }
+            Rev::MessageSearch { pattern } => self.search_messages(&pattern),
+            Rev::TreePath { rev, path } => {
+                let oid = self.resolve_query(*rev)?;
+                self.tree_path(&oid, &path)
+            }
         }
     }
 
@@ -130,6 +247,8 @@ impl<'a> Revision<'a> {
         let symref = self.repo.refs.read_ref(name);
         if symref.is_some() {
             symref
+        } else if !OID_PREFIX.is_match(name) {
+            None
         } else {
             let candidates = self.repo.database.prefix_match(name);
             if candidates.len() == 1 {
@@ -169,11 +288,175 @@ impl<'a> Revision<'a> {
         self.errors.push(HintedError { message, hint });
     }
 
-    fn commit_parent(&mut self, oid: &str) -> Option<String> {
+    fn commit_parents(&mut self, oid: &str) -> Vec<String> {
         match self.load_commit(oid) {
-            Some(commit) => commit.parent.clone(),
-            None => None,
+            Some(commit) => commit.parents.clone(),
+            None => vec![],
+        }
+    }
+
+    /// Find the remote-tracking branch `branch` merges from, using
+    /// the `branch.<name>.remote` and `branch.<name>.merge` settings.
+    fn upstream(&mut self, branch: Option<String>) -> Option<String> {
+        let branch = match branch {
+            Some(branch) => branch.trim_start_matches("refs/heads/").to_string(),
+            None => {
+                let current = self.repo.refs.current_ref("HEAD");
+                if current.is_head() {
+                    self.push_error("HEAD does not point to a branch".to_string());
+                    return None;
+                }
+                self.repo.refs.ref_short_name(&current)
+            }
+        };
+
+        if let Err(e) = self.repo.config.load() {
+            self.push_error(e.to_string());
+            return None;
+        }
+        let remote = self.repo.config.get(&["branch", &branch, "remote"]);
+        let merge = self.repo.config.get(&["branch", &branch, "merge"]);
+
+        let (remote, merge) = match (remote, merge) {
+            (Some(remote), Some(merge)) => (remote, merge),
+            _ => {
+                self.push_error(format!("no upstream configured for branch '{}'", branch));
+                return None;
+            }
+        };
+
+        let merge = merge.trim_start_matches("refs/heads/");
+        let tracking_ref = if remote == "." {
+            format!("refs/heads/{}", merge)
+        } else {
+            format!("refs/remotes/{}/{}", remote, merge)
+        };
+
+        let oid = self.repo.refs.read_ref(&tracking_ref);
+        if oid.is_none() {
+            self.push_error(format!(
+                "upstream branch '{}' not stored as a remote-tracking branch",
+                tracking_ref
+            ));
+        }
+        oid
+    }
+
+    /// Search the history of HEAD and every branch for the most
+    /// recent commit whose message matches `pattern`.
+    fn search_messages(&mut self, pattern: &str) -> Option<String> {
+        let regex = match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                self.push_error(e.to_string());
+                return None;
+            }
+        };
+
+        let mut queue: Vec<String> = self.repo.refs.read_head().into_iter().collect();
+        let branches = match self.repo.refs.list_branches() {
+            Ok(branches) => branches,
+            Err(e) => {
+                self.push_error(e.to_string());
+                return None;
+            }
+        };
+        for branch in branches {
+            queue.extend(self.repo.refs.read_oid(&branch));
+        }
+
+        let mut seen = HashSet::new();
+        let mut best: Option<(String, commit::Commit)> = None;
+
+        while let Some(oid) = queue.pop() {
+            if !seen.insert(oid.clone()) {
+                continue;
+            }
+            let commit = match self.load_commit(&oid) {
+                Some(commit) => commit.clone(),
+                None => continue,
+            };
+
+            queue.extend(commit.parents.iter().cloned());
+
+            if !regex.is_match(&commit.message) {
+                continue;
+            }
+            let is_newer = best
+                .as_ref()
+                .map(|(_, other)| commit.committer.time > other.committer.time)
+                .unwrap_or(true);
+            if is_newer {
+                best = Some((oid, commit));
+            }
+        }
+
+        if best.is_none() {
+            self.push_error(format!("no commit message matches '{}'", pattern));
+        }
+        best.map(|(oid, _)| oid)
+    }
+
+    /// Look up `path` in the tree of the commit or tree `oid`. An
+    /// empty path names the tree itself.
+    fn tree_path(&mut self, oid: &str, path: &str) -> Option<String> {
+        let mut current = match self.repo.database.load(oid) {
+            ParsedObject::Commit(commit) => commit.tree_oid.clone(),
+            ParsedObject::Tree(_) => oid.to_string(),
+            object => {
+                let message = format!("object {} is a {}, not a tree", oid, object.obj_type());
+                self.push_error(message);
+                return None;
+            }
+        };
+
+        for component in Path::new(path).iter() {
+            let name = component.to_str().expect("invalid path");
+            let entry = match self.repo.database.load(&current) {
+                ParsedObject::Tree(tree) => tree.entries.get(name).cloned(),
+                _ => None,
+            };
+
+            match entry {
+                Some(entry) => current = entry.get_oid(),
+                None => {
+                    self.push_error(format!("path '{}' does not exist in '{}'", path, oid));
+                    return None;
+                }
+            }
         }
+
+        Some(current)
+    }
+
+    /// Follow annotated tags through to the object they point at
+    fn peel(&mut self, oid: &str) -> Option<String> {
+        let mut oid = oid.to_string();
+        while let ParsedObject::Tag(tag) = self.load_object(&oid)? {
+            oid = tag.object.clone();
+        }
+        Some(oid)
+    }
+
+    /// Load `oid`, recording why as an error if it can't be read
+    fn load_object(&mut self, oid: &str) -> Option<&ParsedObject> {
+        match self.repo.database.try_load(oid) {
+            Ok(object) => Some(object),
+            Err(error) => {
+                self.errors.push(HintedError {
+                    message: error.trim_end().trim_start_matches("fatal: ").to_string(),
+                    hint: vec![],
+                });
+                None
+            }
+        }
+    }
+
+    fn push_error(&mut self, message: String) {
+        self.errors.push(HintedError {
+            message,
+            hint: vec![],
+        });
     }
 
     fn load_commit(&mut self, oid: &str) -> Option<&commit::Commit> {
@@ -2,7 +2,7 @@ use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::fmt::Write;
 use std::num::ParseIntError;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
     (0..s.len())
@@ -30,3 +30,74 @@ pub fn relative_path_from(path: &Path, from: &Path) -> String {
         .unwrap()
         .to_string()
 }
+
+/// Drop `.` components and trailing slashes so arguments match the
+/// paths stored in the index
+pub fn normalize_path(arg: &str) -> String {
+    let path: PathBuf = Path::new(arg)
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    path.to_str().unwrap().to_string()
+}
+
+/// Turn an expiry setting such as `90.days.ago`, `2 weeks ago`, `now`,
+/// `never` or a Unix timestamp into a cutoff time: anything dated at or
+/// before the cutoff has expired.
+pub fn parse_expiry(value: &str, now: i64) -> Result<i64, String> {
+    let value = value.trim().to_lowercase();
+    match value.as_str() {
+        "never" | "false" => return Ok(i64::MIN),
+        "now" | "all" => return Ok(now),
+        _ => (),
+    }
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return Ok(timestamp);
+    }
+
+    let words: Vec<&str> = value
+        .split(|c: char| c == '.' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let (count, unit) = match words.as_slice() {
+        [count, unit, "ago"] | [count, unit] => (count, unit.trim_end_matches('s')),
+        _ => return Err(format!("invalid expiry date '{}'", value)),
+    };
+    let count: i64 = count
+        .parse()
+        .map_err(|_| format!("invalid expiry date '{}'", value))?;
+    let seconds = match unit {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return Err(format!("invalid expiry date '{}'", value)),
+    };
+
+    count
+        .checked_mul(seconds)
+        .and_then(|age| now.checked_sub(age))
+        .ok_or_else(|| format!("invalid expiry date '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_expiry_dates() {
+        let now = 1_600_000_000;
+        assert_eq!(Ok(now - 90 * 86400), parse_expiry("90.days.ago", now));
+        assert_eq!(Ok(now - 2 * 604_800), parse_expiry("2 weeks ago", now));
+        assert_eq!(Ok(now - 3600), parse_expiry("1.hour", now));
+        assert_eq!(Ok(now), parse_expiry("now", now));
+        assert_eq!(Ok(i64::MIN), parse_expiry("never", now));
+        assert_eq!(Ok(1234), parse_expiry("1234", now));
+        assert!(parse_expiry("soon", now).is_err());
+        assert!(parse_expiry("3.fortnights.ago", now).is_err());
+        assert!(parse_expiry("99999999999999 weeks ago", now).is_err());
+    }
+}